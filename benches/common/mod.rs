@@ -0,0 +1,40 @@
+//! Configs shared by the benchmarks.
+
+#![allow(dead_code)]
+
+use survival_rn::{
+    policy::PolicyKind,
+    util::HEPT32,
+    world::{species::SpeciesConfig, SimConfig},
+};
+
+/// The default game, with `species` and `num_food` bushes.
+pub const fn config(species: &'static [SpeciesConfig], num_food: usize) -> SimConfig {
+    SimConfig {
+        num_food,
+        species,
+        ..SimConfig::DEFAULT
+    }
+}
+
+/// Two species controlled by `policy`: `packs` packs of four, and `4 * packs` loners.
+pub const fn species(policy: PolicyKind, packs: usize) -> [SpeciesConfig; 2] {
+    [
+        SpeciesConfig {
+            color: HEPT32::RED,
+            num_creatures: 4,
+            num_packs: packs,
+            policy,
+            signals: 2,
+            nocturnal: false,
+        },
+        SpeciesConfig {
+            color: HEPT32::BLUE,
+            num_creatures: 1,
+            num_packs: packs * 4,
+            policy,
+            signals: 2,
+            nocturnal: true,
+        },
+    ]
+}
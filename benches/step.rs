@@ -0,0 +1,31 @@
+//! Times the step loop of a world controlled by random policies. Run with
+//! `cargo bench --bench step`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use survival_rn::{
+    policy::{Policy, PolicyKind, RandomPolicy},
+    world::{species::SpeciesConfig, SimConfig, World},
+};
+
+mod common;
+
+const SPECIES: &[SpeciesConfig] = &common::species(PolicyKind::Random, 4);
+const CONFIG: SimConfig = common::config(SPECIES, 20);
+
+fn step(c: &mut Criterion) {
+    // Only the first moon is played, since random creatures rarely survive the next one.
+    c.bench_function("World::step, 1 moon", |b| {
+        b.iter_batched_ref(
+            || World::new(CONFIG),
+            |world| {
+                for _ in 0..CONFIG.moon_len {
+                    world.step(|_, state| RandomPolicy.choose_action(state));
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
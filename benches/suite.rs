@@ -0,0 +1,241 @@
+//! Times world generation, observation extraction, action handling, DQN inference, and the rollout
+//! and learning halves of training, for a few population counts. Run with
+//! `cargo bench --bench suite`.
+//!
+//! Only the grid size set in `util.rs` is benchmarked. [`GRID_WIDTH`] and [`GRID_HEIGHT`] are
+//! constants that the sizes of the grid's arrays are built from, so they can't be varied at
+//! runtime. The size is part of every group name, so results of runs with different sizes are
+//! never compared against each other.
+
+use std::hint::black_box;
+
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BatchSize, BenchmarkGroup, BenchmarkId,
+    Criterion,
+};
+use survival_rn::{
+    policy::{DqnConfig, Policy, PolicyKind, RandomPolicy},
+    train::{
+        parallel::{learn, rollout},
+        replay::{ReplayBuffer, ReplayConfig},
+    },
+    util::{GRID_HEIGHT, GRID_WIDTH},
+    world::{
+        creature::{CreatureAction, CreatureState},
+        observation::ObservationEncoder,
+        rules::{DefaultRules, Rules},
+        species::SpeciesConfig,
+        SimConfig, World,
+    },
+};
+
+mod common;
+
+use common::{config, species};
+
+/// Number of bushes in every benchmarked world.
+const NUM_FOOD: usize = 20;
+
+/// Populations of 8, 32 and 64 creatures for each policy.
+const RANDOM: [(usize, &[SpeciesConfig]); 3] = [
+    (8, &species(PolicyKind::Random, 1)),
+    (32, &species(PolicyKind::Random, 4)),
+    (64, &species(PolicyKind::Random, 8)),
+];
+const TABULAR: [(usize, &[SpeciesConfig]); 3] = [
+    (8, &species(PolicyKind::Tabular, 1)),
+    (32, &species(PolicyKind::Tabular, 4)),
+    (64, &species(PolicyKind::Tabular, 8)),
+];
+const DQN: [(usize, &[SpeciesConfig]); 3] = [
+    (8, &species(PolicyKind::Dqn(DqnConfig::DEFAULT), 1)),
+    (32, &species(PolicyKind::Dqn(DqnConfig::DEFAULT), 4)),
+    (64, &species(PolicyKind::Dqn(DqnConfig::DEFAULT), 8)),
+];
+
+/// Group of benchmarks named `name` and the grid size. Their inputs are the number of creatures.
+fn group<'a>(c: &'a mut Criterion, name: &str) -> BenchmarkGroup<'a, WallTime> {
+    c.benchmark_group(format!("{name} ({GRID_WIDTH}x{GRID_HEIGHT} grid)"))
+}
+
+fn world(c: &mut Criterion) {
+    let mut group = group(c, "world");
+
+    for (creatures, species) in RANDOM {
+        let config = config(species, NUM_FOOD);
+
+        group.bench_function(BenchmarkId::new("World::new", creatures), |b| {
+            b.iter(|| World::new(config))
+        });
+        group.bench_function(
+            BenchmarkId::new("Species::get_view_slice", creatures),
+            |b| {
+                b.iter_batched_ref(
+                    || World::new(config),
+                    |world| {
+                        for species in &world.species {
+                            for index in 0..species.members.len() {
+                                black_box(species.get_view_slice(&world.grid, index));
+                            }
+                        }
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+        group.bench_function(BenchmarkId::new("observations", creatures), |b| {
+            let mut observation = vec![0.0; ObservationEncoder::DEFAULT.size()];
+            b.iter_batched_ref(
+                || World::new(config),
+                |world| {
+                    for species in &world.species {
+                        for index in 0..species.members.len() {
+                            let state = CreatureState::new(world, species, 0, index);
+                            ObservationEncoder::DEFAULT.encode_into(&state, &mut observation);
+                            black_box(&observation);
+                        }
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(
+            BenchmarkId::new("ObservationEncoder::encode_species", creatures),
+            |b| {
+                let mut batch = vec![0.0; ObservationEncoder::DEFAULT.size() * creatures];
+                b.iter_batched_ref(
+                    || World::new(config),
+                    |world| {
+                        for species in &world.species {
+                            let len = ObservationEncoder::DEFAULT.size() * species.members.len();
+                            ObservationEncoder::DEFAULT.encode_species(
+                                world,
+                                species,
+                                0,
+                                &mut batch[..len],
+                            );
+                            black_box(&batch);
+                        }
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+        group.bench_function(BenchmarkId::new("Rules::handle_action", creatures), |b| {
+            b.iter_batched_ref(
+                || with_actions(config),
+                |(world, actions)| {
+                    for (species, index, action) in actions {
+                        DefaultRules::handle_action(world, *species, *action, *index);
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("World::finish_step", creatures), |b| {
+            b.iter_batched_ref(
+                || World::new(config),
+                |world| world.finish_step(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("World::step", creatures), |b| {
+            b.iter_batched_ref(
+                || World::new(config),
+                |world| world.step(|_, state| RandomPolicy.choose_action(state)),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+fn dqn(c: &mut Criterion) {
+    let mut group = group(c, "DQN");
+    group.sample_size(20);
+
+    for (creatures, species) in DQN {
+        let config = config(species, NUM_FOOD);
+
+        group.bench_function(BenchmarkId::new("World::step", creatures), |b| {
+            b.iter_batched_ref(
+                || (World::new(config), config.create_controllers()),
+                |(world, controllers)| world.step(|id, state| controllers[id].choose_action(state)),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("World::step_batched", creatures), |b| {
+            b.iter_batched_ref(
+                || (World::new(config), config.create_controllers()),
+                |(world, controllers)| {
+                    world.step_batched(|world, id| controllers[id].choose_actions(world, id))
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+}
+
+/// Times playing out a moon and learning from it separately. The rollout is only timed once per
+/// population, since learning species all explore at random.
+fn training(c: &mut Criterion) {
+    let mut group = group(c, "training");
+    group.sample_size(10);
+
+    for (creatures, species) in TABULAR {
+        let config = config(species, NUM_FOOD);
+
+        group.bench_function(BenchmarkId::new("rollout, 1 moon", creatures), |b| {
+            b.iter_batched(
+                || World::new(config),
+                |world| rollout(world, 1),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    for (kind, populations) in [("tabular", TABULAR), ("DQN", DQN)] {
+        for (creatures, species) in populations {
+            let config: SimConfig = config(species, NUM_FOOD);
+            let transitions = rollout(World::new(config), 1);
+
+            group.bench_function(
+                BenchmarkId::new(format!("learn, 1 moon, {kind}"), creatures),
+                |b| {
+                    b.iter_batched_ref(
+                        || {
+                            let controllers = config.create_controllers();
+                            let replays =
+                                vec![ReplayBuffer::new(ReplayConfig::DEFAULT); controllers.len()];
+                            (controllers, replays)
+                        },
+                        |(controllers, replays)| learn(controllers, replays, &transitions),
+                        BatchSize::LargeInput,
+                    )
+                },
+            );
+        }
+    }
+}
+
+/// A fresh world, and the action every creature in it picks at random, in the order they act.
+/// The actions are picked while playing the step out on a copy of the world, so each one is still
+/// legal after the creatures before it acted.
+fn with_actions(config: SimConfig) -> (World, Vec<(usize, usize, CreatureAction)>) {
+    let world = World::new(config);
+    let mut played = world.clone();
+    let mut actions = Vec::new();
+
+    for id in 0..played.species.len() {
+        for index in 0..played.species[id].members.len() {
+            let state = CreatureState::new(&played, &played.species[id], 0, index);
+            let (action, _) = RandomPolicy.choose_action(&state);
+            DefaultRules::handle_action(&mut played, id, action, index);
+            actions.push((id, index, action));
+        }
+    }
+
+    (world, actions)
+}
+
+criterion_group!(benches, world, dqn, training);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+//! A survival simulation where species of creatures learn to gather food and fight over it.
+//!
+//! The simulation itself lives in [`world`] and has no dependencies beyond `rand`. Training with
+//! reinforcement learning (`train`) and drawing the world (`render`) are behind the features of
+//! the same names, both enabled by default.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use survival_rn::{
+//!     policy::{GreedyPolicy, Policy, PolicyKind},
+//!     util::HEPT32,
+//!     world::{day::DayCycle, species::SpeciesConfig, SimConfig, World},
+//! };
+//!
+//! // Start from the default game, with a single species that doesn't learn.
+//! const CONFIG: SimConfig = SimConfig {
+//!     day_cycle: DayCycle::ALWAYS_DAY,
+//!     species: &[SpeciesConfig {
+//!         color: HEPT32::RED,
+//!         num_creatures: 4,
+//!         num_packs: 2,
+//!         policy: PolicyKind::Greedy,
+//!         signals: 0,
+//!         nocturnal: false,
+//!     }],
+//!     ..SimConfig::DEFAULT
+//! };
+//!
+//! let mut world = World::new(CONFIG);
+//!
+//! // Any type implementing `Policy` can control creatures.
+//! let mut policy = GreedyPolicy;
+//! while !world.is_extinct() && world.moon < 10 {
+//!     world.step(|_species, state| policy.choose_action(state));
+//! }
+//!
+//! println!("{} creatures survived.", world.population());
+//! ```
+
+pub mod policy;
+#[cfg(feature = "render")]
+pub mod render;
+#[cfg(feature = "train")]
+pub mod train;
+pub mod util;
+pub mod world;
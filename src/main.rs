@@ -1,35 +1,51 @@
-use std::io;
-
-use render::run_simulation;
-use train::train_iters;
-use util::HEPT32;
-use world::{species::SpeciesConfig, SimConfig, World};
-
-mod render;
-mod train;
-mod util;
-mod world;
-
-const CONFIG: SimConfig = SimConfig {
-    moon_len: 20,
-    num_food: 20,
-    chance_regrow: 0.5,
-    species: &[
-        SpeciesConfig {
-            color: HEPT32::RED,
-            num_creatures: 4,
-            num_packs: 2,
-        },
-        SpeciesConfig {
-            color: HEPT32::BLUE,
-            num_creatures: 1,
-            num_packs: 8,
-        },
-    ],
+use std::{env, io, path::Path, thread, time::Instant};
+
+use survival_rn::{
+    render::{export::record_simulation, run_simulation},
+    train::{
+        parallel::train_parallel_with,
+        replay::{ReplayBuffer, ReplayConfig},
+    },
+    world::{SimConfig, World},
 };
 
+const CONFIG: SimConfig = SimConfig::DEFAULT;
+
+/// Number of training epochs, each of which plays one world per thread.
+const EPOCHS: usize = 300;
+/// Number of moons played in each world.
+const MOONS: usize = 10;
+
+/// Number of steps to record when exporting frames instead of opening a window.
+const RECORD_STEPS: usize = CONFIG.moon_len * 5;
+
 fn main() {
-    let models = train_iters(CONFIG, 300, 10);
+    let num_envs = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut policies = CONFIG.create_controllers();
+    let mut replays = vec![ReplayBuffer::new(ReplayConfig::DEFAULT); policies.len()];
+
+    for epoch in 0..EPOCHS {
+        let now = Instant::now();
+        train_parallel_with(CONFIG, &mut policies, &mut replays, 1, MOONS, num_envs);
+        println!(
+            "# Epoch {}/{EPOCHS} ({num_envs} worlds) completed in {:.1} seconds.",
+            epoch + 1,
+            now.elapsed().as_secs_f64()
+        );
+    }
+
+    // Passing a path records the simulation to it instead of opening a window.
+    if let Some(path) = env::args().nth(1) {
+        println!("Recording simulation to {path}.");
+        record_simulation(
+            &mut World::new(CONFIG),
+            policies,
+            RECORD_STEPS,
+            Path::new(&path),
+        )
+        .expect("Failed to export frames.");
+        return;
+    }
 
     println!("Press ENTER to start simulation.");
     let mut buf = String::new();
@@ -37,5 +53,5 @@ fn main() {
         .read_line(&mut buf)
         .expect("Failed to read input.");
 
-    run_simulation(&mut World::new(CONFIG), models);
+    run_simulation(&mut World::new(CONFIG), policies);
 }
@@ -0,0 +1,187 @@
+//! Controllers that decide what creatures do.
+
+use crate::world::{
+    creature::{CreatureAction, CreatureState},
+    grid::Pos,
+    observation::ObservationEncoder,
+    rules::{DefaultRules, Rules},
+    World,
+};
+
+/// Picks an action for a creature based on what it can see, and a signal to send alongside it.
+pub trait Policy<R: Rules = DefaultRules> {
+    /// Should return one of [`Rules::actions`] for `state`, and a signal for allies in view: zero
+    /// for none, or up to [`CreatureState::num_signals`].
+    fn choose_action(&mut self, state: &CreatureState<R>) -> (CreatureAction, u8);
+
+    /// Picks an action and signal for each member of species `id` in `world`, in order. Policies
+    /// that are faster on many creatures at once, like a neural network, should override this.
+    /// See [`World::step_batched`].
+    fn choose_actions(&mut self, world: &World<R>, id: usize) -> Vec<(CreatureAction, u8)> {
+        let species = &world.species[id];
+        (0..species.members.len())
+            .map(|index| {
+                self.choose_action(&CreatureState::new(
+                    world,
+                    species,
+                    world.moon_step(),
+                    index,
+                ))
+            })
+            .collect()
+    }
+}
+
+impl<R: Rules, P: Policy<R> + ?Sized> Policy<R> for Box<P> {
+    fn choose_action(&mut self, state: &CreatureState<R>) -> (CreatureAction, u8) {
+        (**self).choose_action(state)
+    }
+
+    fn choose_actions(&mut self, world: &World<R>, id: usize) -> Vec<(CreatureAction, u8)> {
+        (**self).choose_actions(world, id)
+    }
+}
+
+/// Which policy a species is controlled by. Set per species in
+/// [`SpeciesConfig`](crate::world::species::SpeciesConfig).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PolicyKind {
+    /// Deep Q-network, trained with reinforcement learning. Requires the `train` feature.
+    Dqn(DqnConfig),
+    /// Q-table over the tiles next to the creature, trained with reinforcement learning. Requires
+    /// the `train` feature.
+    Tabular,
+    /// [`RandomPolicy`]
+    Random,
+    /// [`GreedyPolicy`]
+    Greedy,
+}
+
+/// Hyperparameters of a [`PolicyKind::Dqn`] network.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DqnConfig {
+    /// Number of neurons in each of the two hidden layers.
+    pub hidden_size: usize,
+    pub learning_rate: f32,
+    /// How much the value of the next state counts towards the value of an action, from 0 to 1.
+    pub gamma: f32,
+    /// Number of transitions in each training batch.
+    pub batch_size: usize,
+    /// Number of gradient steps taken on each batch.
+    pub steps_per_batch: usize,
+    /// Number of gradient steps between copies of the network into the target network, which
+    /// estimates the value of next states.
+    pub target_sync: usize,
+    /// Whether the best next action is picked by the network and valued by the target network
+    /// (double DQN), instead of both by the target network. Overestimates values less.
+    pub double: bool,
+    /// What the network sees. Its number of inputs is [`ObservationEncoder::size`].
+    pub observation: ObservationEncoder,
+}
+
+impl DqnConfig {
+    pub const DEFAULT: Self = Self {
+        hidden_size: 128,
+        learning_rate: 1e-3,
+        gamma: 0.99,
+        batch_size: 64,
+        steps_per_batch: 20,
+        target_sync: 20,
+        double: false,
+        observation: ObservationEncoder::DEFAULT,
+    };
+}
+
+impl Default for DqnConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Takes a uniformly random legal action, and sends a uniformly random signal (or none).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RandomPolicy;
+
+impl<R: Rules> Policy<R> for RandomPolicy {
+    fn choose_action(&mut self, state: &CreatureState<R>) -> (CreatureAction, u8) {
+        (random_action(&R::actions(state)), random_signal(state))
+    }
+}
+
+/// Walks towards the nearest food in view and eats it, wandering randomly if there is none. Never
+/// signals.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct GreedyPolicy;
+
+impl<R: Rules> Policy<R> for GreedyPolicy {
+    fn choose_action(&mut self, state: &CreatureState<R>) -> (CreatureAction, u8) {
+        (greedy_action(state), 0)
+    }
+}
+
+fn greedy_action<R: Rules>(state: &CreatureState<R>) -> CreatureAction {
+    let actions = R::actions(state);
+
+    if let Some(Pos(x, y)) = nearest_food(state) {
+        let (dx, dy) = (x as i8 - 3, y as i8 - 3);
+
+        for direction in [(dx.signum(), 0), (0, dy.signum())] {
+            if direction == (0, 0) {
+                continue;
+            }
+
+            let attack = CreatureAction::Attack(direction.0, direction.1);
+            if state.slice[Pos(3, 3) + direction].has_food() && actions.contains(&attack) {
+                return attack;
+            }
+
+            let step = CreatureAction::Move(direction.0, direction.1);
+            if actions.contains(&step) {
+                return step;
+            }
+        }
+    }
+
+    let moves: Vec<CreatureAction> = actions
+        .iter()
+        .copied()
+        .filter(|action| matches!(action, CreatureAction::Move(..)))
+        .collect();
+
+    if moves.is_empty() {
+        random_action(&actions)
+    } else {
+        random_action(&moves)
+    }
+}
+
+fn nearest_food<R: Rules>(state: &CreatureState<R>) -> Option<Pos> {
+    let mut nearest = None;
+    let mut nearest_distance = usize::MAX;
+
+    for (y, row) in state.slice.arr().iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            if tile.has_food() {
+                let distance = x.abs_diff(3) + y.abs_diff(3);
+                if distance < nearest_distance {
+                    nearest = Some(Pos(x, y));
+                    nearest_distance = distance;
+                }
+            }
+        }
+    }
+
+    nearest
+}
+
+fn random_action(actions: &[CreatureAction]) -> CreatureAction {
+    actions
+        .get(rand::random::<usize>() % actions.len().max(1))
+        .copied()
+        .unwrap_or(CreatureAction::DoNothing)
+}
+
+/// Uniformly random signal that `state`'s creature can send, including zero for none.
+fn random_signal<R: Rules>(state: &CreatureState<R>) -> u8 {
+    (rand::random::<usize>() % (state.num_signals as usize + 1)) as u8
+}
@@ -7,11 +7,19 @@ pub struct Assets {
     pub agent: Texture2D,
     pub bush: Texture2D,
     pub bush_berries: Texture2D,
+    pub roots: Texture2D,
+    pub roots_dug: Texture2D,
+    pub fruit_tree: Texture2D,
+    pub fruit_tree_fruit: Texture2D,
+    pub carcass: Texture2D,
+    pub cache: Texture2D,
     pub wall: Texture2D,
+    pub nest: Texture2D,
+    pub trap: Texture2D,
 }
 
 fn load_texture(path: &str, rl: &mut RaylibHandle, thread: &RaylibThread) -> Texture2D {
-    rl.load_texture_from_image(&thread, &Image::load_image(path).expect("Image not found."))
+    rl.load_texture_from_image(thread, &Image::load_image(path).expect("Image not found."))
         .expect("Failed to load texture")
 }
 
@@ -20,6 +28,14 @@ pub fn load(rl: &mut RaylibHandle, thread: &RaylibThread) -> Assets {
         agent: load_texture("assets/agent.png", rl, thread),
         bush: load_texture("assets/bush.png", rl, thread),
         bush_berries: load_texture("assets/bush_berries.png", rl, thread),
+        roots: load_texture("assets/roots.png", rl, thread),
+        roots_dug: load_texture("assets/roots_dug.png", rl, thread),
+        fruit_tree: load_texture("assets/fruit_tree.png", rl, thread),
+        fruit_tree_fruit: load_texture("assets/fruit_tree_fruit.png", rl, thread),
+        carcass: load_texture("assets/carcass.png", rl, thread),
+        cache: load_texture("assets/cache.png", rl, thread),
         wall: load_texture("assets/wall.png", rl, thread),
+        nest: load_texture("assets/nest.png", rl, thread),
+        trap: load_texture("assets/trap.png", rl, thread),
     }
 }
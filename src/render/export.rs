@@ -0,0 +1,266 @@
+//! Headless rendering of the simulation to PNG frames or an animated GIF. Everything here runs on
+//! the CPU, so no window or display is needed.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use raylib::texture::Image;
+
+use crate::{
+    policy::Policy,
+    util::{gif::GifEncoder, png, Color, GRID_HEIGHT, GRID_WIDTH, TILE_SIZE},
+    world::{day::Phase, rules::Rules, World},
+};
+
+/// Factor that frames are upscaled by before being written.
+const SCALE: usize = 2;
+/// How long each step is shown for in an animated GIF, in hundredths of a second.
+const GIF_DELAY: u16 = 25;
+
+/// 3x5 pixel glyphs for the digits 0-9, one row per byte.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b011, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+const MINUS: [u8; 5] = [0b000, 0b000, 0b111, 0b000, 0b000];
+
+pub struct Sprite {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Sprite {
+    fn load(path: &str) -> Self {
+        let image = Image::load_image(path).expect("Image not found.");
+
+        Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image
+                .get_image_data()
+                .iter()
+                .map(|color| Color::new(color.r, color.g, color.b, color.a))
+                .collect(),
+        }
+    }
+}
+
+/// CPU-side counterpart to [`Assets`](super::assets::Assets).
+pub struct Sprites {
+    pub agent: Sprite,
+    pub bush: Sprite,
+    pub bush_berries: Sprite,
+    pub roots: Sprite,
+    pub roots_dug: Sprite,
+    pub fruit_tree: Sprite,
+    pub fruit_tree_fruit: Sprite,
+    pub carcass: Sprite,
+    pub cache: Sprite,
+    pub wall: Sprite,
+    pub nest: Sprite,
+    pub trap: Sprite,
+}
+
+pub fn load_sprites() -> Sprites {
+    Sprites {
+        agent: Sprite::load("assets/agent.png"),
+        bush: Sprite::load("assets/bush.png"),
+        bush_berries: Sprite::load("assets/bush_berries.png"),
+        roots: Sprite::load("assets/roots.png"),
+        roots_dug: Sprite::load("assets/roots_dug.png"),
+        fruit_tree: Sprite::load("assets/fruit_tree.png"),
+        fruit_tree_fruit: Sprite::load("assets/fruit_tree_fruit.png"),
+        carcass: Sprite::load("assets/carcass.png"),
+        cache: Sprite::load("assets/cache.png"),
+        wall: Sprite::load("assets/wall.png"),
+        nest: Sprite::load("assets/nest.png"),
+        trap: Sprite::load("assets/trap.png"),
+    }
+}
+
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize, background: Color) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width * height],
+        }
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Alpha blends `color` onto the pixel at (x, y). Pixels outside of the frame are ignored.
+    pub fn blend(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+
+        let dst = &mut self.pixels[y as usize * self.width + x as usize];
+        let alpha = color.a as u32;
+        let mix =
+            |src: u8, dst: u8| ((src as u32 * alpha + dst as u32 * (255 - alpha)) / 255) as u8;
+
+        *dst = Color::new(
+            mix(color.r, dst.r),
+            mix(color.g, dst.g),
+            mix(color.b, dst.b),
+            dst.a.max(color.a),
+        );
+    }
+
+    /// Draws a sprite with its top left corner at (x, y), multiplying each pixel by `tint` the
+    /// same way raylib does.
+    pub fn draw_sprite(&mut self, sprite: &Sprite, x: i32, y: i32, tint: Color) {
+        let tint_channel = |src: u8, tint: u8| ((src as u32 * tint as u32) / 255) as u8;
+
+        for sy in 0..sprite.height {
+            for sx in 0..sprite.width {
+                let src = sprite.pixels[sy * sprite.width + sx];
+                self.blend(
+                    x + sx as i32,
+                    y + sy as i32,
+                    Color::new(
+                        tint_channel(src.r, tint.r),
+                        tint_channel(src.g, tint.g),
+                        tint_channel(src.b, tint.b),
+                        tint_channel(src.a, tint.a),
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Draws an integer using a tiny built-in pixel font.
+    pub fn draw_number(&mut self, number: isize, x: i32, y: i32, color: Color) {
+        let mut glyphs = Vec::new();
+        if number < 0 {
+            glyphs.push(MINUS);
+        }
+        for digit in number.unsigned_abs().to_string().bytes() {
+            glyphs.push(DIGITS[(digit - b'0') as usize]);
+        }
+
+        for (i, glyph) in glyphs.iter().enumerate() {
+            for (gy, row) in glyph.iter().enumerate() {
+                for gx in 0..3 {
+                    if row & (0b100 >> gx) != 0 {
+                        self.blend(x + i as i32 * 4 + gx, y + gy as i32, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a copy of the frame upscaled by an integer factor with nearest neighbour sampling.
+    pub fn scaled(&self, scale: usize) -> Frame {
+        let (width, height) = (self.width * scale, self.height * scale);
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.pixels[(y / scale) * self.width + x / scale]);
+            }
+        }
+
+        Frame {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        png::encode(
+            &mut BufWriter::new(File::create(path)?),
+            self.width,
+            self.height,
+            &self.pixels,
+        )
+    }
+}
+
+/// Renders the current state of the world to a new frame.
+pub fn render_frame<R: Rules>(world: &World<R>, sprites: &Sprites) -> Frame {
+    let mut frame = Frame::new(
+        GRID_WIDTH * TILE_SIZE as usize,
+        GRID_HEIGHT * TILE_SIZE as usize,
+        Color::TAN,
+    );
+    let colors: Vec<Color> = world.species.iter().map(|s| s.color).collect();
+    world.scent.rasterize(&mut frame, &colors);
+    world.grid.rasterize(&mut frame, sprites);
+
+    if world.phase() == Phase::Night {
+        for y in 0..frame.height as i32 {
+            for x in 0..frame.width as i32 {
+                frame.blend(x, y, super::NIGHT_TINT);
+            }
+        }
+    }
+
+    frame.scaled(SCALE)
+}
+
+/// Runs the simulation for `num_steps` steps without opening a window, saving one frame per step.
+///
+/// If `path` ends in `.gif` the frames are written as a single animated GIF, otherwise `path` is
+/// treated as a directory and each frame is written to it as a numbered PNG.
+pub fn record_simulation<R: Rules, P: Policy<R>>(
+    world: &mut World<R>,
+    mut policies: Vec<P>,
+    num_steps: usize,
+    path: &Path,
+) -> io::Result<()> {
+    let sprites = load_sprites();
+
+    let mut gif = if path.extension().is_some_and(|ext| ext == "gif") {
+        let frame = render_frame(world, &sprites);
+        Some(GifEncoder::new(
+            BufWriter::new(File::create(path)?),
+            frame.width,
+            frame.height,
+            GIF_DELAY,
+        )?)
+    } else {
+        fs::create_dir_all(path)?;
+        None
+    };
+
+    for step in 0..=num_steps {
+        if step > 0 {
+            world.step_batched(|world, species| policies[species].choose_actions(world, species));
+        }
+
+        let frame = render_frame(world, &sprites);
+        match &mut gif {
+            Some(gif) => gif.add_frame(frame.pixels())?,
+            None => frame.save_png(&path.join(format!("frame_{step:05}.png")))?,
+        }
+    }
+
+    if let Some(gif) = gif {
+        gif.finish()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+use raylib::{color::Color, drawing::RaylibDraw, math::Vector2};
+
+use crate::world::{rules::Rules, World};
+
+pub const HUD_WIDTH: i32 = 240;
+
+const PADDING: i32 = 10;
+const FONT_SIZE: i32 = 20;
+const LINE_HEIGHT: i32 = 24;
+const GRAPH_HEIGHT: i32 = 160;
+/// Number of steps shown in the population graph before it starts scrolling.
+const HISTORY_LEN: usize = (HUD_WIDTH - PADDING * 2) as usize;
+
+pub struct Hud {
+    /// Population of each species, sampled once per step.
+    history: Vec<VecDeque<usize>>,
+}
+
+impl Hud {
+    pub fn new<R: Rules>(world: &World<R>) -> Self {
+        let mut hud = Self {
+            history: vec![VecDeque::with_capacity(HISTORY_LEN); world.species.len()],
+        };
+        hud.record(world);
+
+        hud
+    }
+
+    pub fn record<R: Rules>(&mut self, world: &World<R>) {
+        for (history, species) in self.history.iter_mut().zip(&world.species) {
+            if history.len() >= HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(species.members.len());
+        }
+    }
+
+    pub fn render<R: Rules>(&self, d: &mut impl RaylibDraw, world: &World<R>, x: i32, height: i32) {
+        d.draw_rectangle(x, 0, HUD_WIDTH, height, Color::BLACK.fade(0.8));
+
+        let x = x + PADDING;
+        let mut y = PADDING;
+
+        d.draw_text(
+            &format!("Moon {}", world.moon + 1),
+            x,
+            y,
+            FONT_SIZE,
+            Color::WHITE,
+        );
+        y += LINE_HEIGHT;
+        d.draw_text(
+            &format!("Steps left: {}", world.time_left),
+            x,
+            y,
+            FONT_SIZE,
+            Color::WHITE,
+        );
+        y += LINE_HEIGHT + PADDING;
+
+        for species in &world.species {
+            d.draw_rectangle(x, y + 4, 12, 12, species.color);
+            d.draw_text(
+                &format!(
+                    "Pop: {}  Food: {}",
+                    species.members.len(),
+                    species.total_food(&world.grid)
+                ),
+                x + 20,
+                y,
+                FONT_SIZE,
+                Color::WHITE,
+            );
+            y += LINE_HEIGHT;
+        }
+        y += PADDING;
+
+        self.render_graph(d, world, x, y);
+    }
+
+    fn render_graph<R: Rules>(&self, d: &mut impl RaylibDraw, world: &World<R>, x: i32, y: i32) {
+        let width = HUD_WIDTH - PADDING * 2;
+        d.draw_rectangle_lines(x, y, width, GRAPH_HEIGHT, Color::WHITE);
+
+        let max = self
+            .history
+            .iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let bottom = (y + GRAPH_HEIGHT - 1) as f32;
+        let scale = (GRAPH_HEIGHT - 2) as f32 / max as f32;
+
+        for (history, species) in self.history.iter().zip(&world.species) {
+            let points: Vec<Vector2> = history
+                .iter()
+                .enumerate()
+                .map(|(i, population)| {
+                    Vector2::new(
+                        (x + 1) as f32 + i as f32,
+                        bottom - *population as f32 * scale,
+                    )
+                })
+                .collect();
+
+            d.draw_line_strip(&points, species.color);
+        }
+
+        d.draw_text(&max.to_string(), x + 2, y + 2, FONT_SIZE / 2, Color::WHITE);
+    }
+}
@@ -1,19 +1,37 @@
+//! Window and headless rendering, behind the `render` feature.
+
 use raylib::{
     camera::Camera2D,
-    color::Color,
+    consts::KeyboardKey,
     drawing::{RaylibDraw, RaylibMode2DExt},
-    ffi::TraceLogLevel,
+    ffi::{self, TraceLogLevel},
     math::Vector2,
 };
-use rurel::strategy::explore::RandomExploration;
 
 use crate::{
-    train::{terminate::FixedIterations, SpeciesAgent, SpeciesModel},
-    util::{GRID_HEIGHT, GRID_WIDTH, TILE_SIZE},
-    world::World,
+    policy::Policy,
+    render::hud::{Hud, HUD_WIDTH},
+    util::{self, GRID_HEIGHT, GRID_WIDTH, TILE_SIZE},
+    world::{day::Phase, rules::Rules, World},
 };
 
 pub mod assets;
+pub mod export;
+pub mod hud;
+
+impl From<util::Color> for ffi::Color {
+    fn from(color: util::Color) -> Self {
+        ffi::Color {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+            a: color.a,
+        }
+    }
+}
+
+/// Drawn over the grid at night.
+const NIGHT_TINT: util::Color = util::Color::new(20, 24, 64, 110);
 
 const ZOOM: f32 = 2.0;
 const VIEW_SIZE: (i32, i32) = (
@@ -21,9 +39,9 @@ const VIEW_SIZE: (i32, i32) = (
     GRID_HEIGHT as i32 * TILE_SIZE * ZOOM as i32,
 );
 
-pub fn run_simulation(world: &mut World, mut models: Vec<SpeciesModel>) {
+pub fn run_simulation<R: Rules, P: Policy<R>>(world: &mut World<R>, mut policies: Vec<P>) {
     let (mut rl, thread) = raylib::init()
-        .size(VIEW_SIZE.0, VIEW_SIZE.1)
+        .size(VIEW_SIZE.0 + HUD_WIDTH, VIEW_SIZE.1)
         .title("Survival Sim")
         .log_level(TraceLogLevel::LOG_ERROR)
         .build();
@@ -36,51 +54,47 @@ pub fn run_simulation(world: &mut World, mut models: Vec<SpeciesModel>) {
         zoom: ZOOM,
     };
 
-    let mut species_data = Vec::new();
-    for (species, model) in world.species.iter().zip(models.iter_mut()) {
-        species_data.push((model, SpeciesAgent::new(&species), species));
-    }
+    let mut hud = Hud::new(world);
+    let colors: Vec<util::Color> = world.species.iter().map(|s| s.color).collect();
+    let mut show_scent = true;
 
     let mut step_timer = 0.0;
-    let mut time_left = world.config.moon_len;
     while !rl.window_should_close() {
         // UPDATE //
+        if rl.is_key_pressed(KeyboardKey::KEY_S) {
+            show_scent = !show_scent;
+        }
+
         step_timer += rl.get_frame_time();
         if step_timer >= 1.0 {
             step_timer = 0.0;
-            time_left -= 1;
-
-            for (trainer, agent, species) in &mut species_data {
-                let iterations = species.members.borrow().len();
-                if iterations == 0 {
-                    continue;
-                }
-
-                agent.reset_index();
-                agent.time = world.config.moon_len - time_left;
-                agent.iters = iterations;
-
-                trainer.train(
-                    agent,
-                    &mut FixedIterations::new(iterations as u32),
-                    &RandomExploration::new(),
-                );
-            }
-
-            world.finish_step();
 
-            if time_left <= 0 {
-                time_left = world.config.moon_len;
-                world.finish_moon();
-            }
+            world.step_batched(|world, species| policies[species].choose_actions(world, species));
+            hud.record(world);
         }
 
         // DRAW //
         let mut d = rl.begin_drawing(&thread);
-        let mut d = d.begin_mode2D(camera);
+        d.clear_background(util::Color::TAN);
 
-        d.clear_background(Color::TAN);
+        {
+            let mut d = d.begin_mode2D(camera);
+            if show_scent {
+                world.scent.render(&mut d, &colors);
+            }
+            world.grid.render(&mut d, &assets);
+
+            if world.phase() == Phase::Night {
+                d.draw_rectangle(
+                    0,
+                    0,
+                    GRID_WIDTH as i32 * TILE_SIZE,
+                    GRID_HEIGHT as i32 * TILE_SIZE,
+                    NIGHT_TINT,
+                );
+            }
+        }
 
-        world.grid.borrow().render(&mut d, &assets);
+        hud.render(&mut d, world, VIEW_SIZE.0, VIEW_SIZE.1);
     }
 }
@@ -1,276 +1,461 @@
-use std::time::Instant;
+use std::marker::PhantomData;
 
 use rurel::{
-    dqn::DQNAgentTrainer,
     mdp::{Agent, State},
-    strategy::explore::RandomExploration,
+    strategy::{explore::RandomExploration, learn::QLearning},
 };
 
-use crate::world::{
-    grid::{Grid, Pos, Tile},
-    species::Species,
-    SimConfig, World,
+use crate::{
+    policy::{GreedyPolicy, Policy, PolicyKind, RandomPolicy},
+    world::{
+        creature::{CreatureAction, CreatureState},
+        rules::{DefaultRules, Rules},
+        species::SpeciesConfig,
+        SimConfig, World,
+    },
 };
+use dqn::Dqn;
+use replay::{Experience, ReplayBuffer, ReplayConfig};
+use tabular::{CompactState, TabularModel};
 use terminate::FixedIterations;
 
+pub mod checkpoint;
+pub mod dqn;
+pub mod parallel;
+pub mod replay;
+pub mod tabular;
 pub mod terminate;
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
-pub enum CreatureAction {
-    Move(i8, i8),
-    Attack(i8, i8),
-    BuildWall(i8, i8),
-    DoNothing,
-}
+impl<R: Rules> State for CreatureState<R> {
+    type A = CreatureAction;
 
-impl CreatureAction {
-    const DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
-}
+    fn reward(&self) -> f64 {
+        R::reward(self)
+    }
 
-impl Into<[f32; 4]> for CreatureAction {
-    fn into(self) -> [f32; 4] {
-        match self {
-            CreatureAction::Move(x, y) => {
-                Self::DIRECTIONS.map(|(x1, y1)| if x1 == x && y1 == y { 1.0 } else { 0.0 })
-            }
-            CreatureAction::Attack(x, y) => {
-                Self::DIRECTIONS.map(|(x1, y1)| if x1 == x && y1 == y { 1.0 } else { 0.0 })
-            }
-            CreatureAction::BuildWall(_, _) => todo!(),
-            CreatureAction::DoNothing => [0.0, 0.0, 0.0, 0.0],
-        }
+    fn actions(&self) -> Vec<Self::A> {
+        R::actions(self)
     }
 }
 
-impl From<[f32; 4]> for CreatureAction {
-    fn from(arr: [f32; 4]) -> Self {
-        let one_hot = arr.map(|v| v.clamp(0.0, 1.0));
+/// Lets the creatures of a species act for rurel, one at a time. `S` is the state the trainer
+/// learns over, built from each creature's [`CreatureState`].
+///
+/// After a creature acts, the agent's state is the one that creature ended up in, so every
+/// transition rurel learns from belongs to a single creature. Move on to the next creature with
+/// [`Self::set_index`].
+pub struct SpeciesAgent<'a, R: Rules = DefaultRules, S = CreatureState<R>> {
+    state: S,
+    world: &'a mut World<R>,
+    species: usize,
+    pub time: usize,
+    creature_index: usize,
+    rules: PhantomData<R>,
+}
 
-        let mut largest_action = (0, 0);
-        let mut largest_confidence = 0.0;
-        for (i, confidence) in one_hot.into_iter().enumerate() {
-            if confidence > largest_confidence {
-                largest_action = Self::DIRECTIONS[i];
-                largest_confidence = confidence;
-            }
+impl<'a, R: Rules, S: From<CreatureState<R>>> SpeciesAgent<'a, R, S> {
+    pub fn new(world: &'a mut World<R>, species: usize) -> Self {
+        Self {
+            state: CreatureState::new(world, &world.species[species], 0, 0).into(),
+            world,
+            species,
+            time: 0,
+            creature_index: 0,
+            rules: PhantomData,
         }
+    }
 
-        if largest_action == (0, 0) {
-            CreatureAction::DoNothing
-        } else {
-            CreatureAction::Move(largest_action.0, largest_action.1)
-        }
+    /// Makes creature `index` the one that acts next.
+    pub fn set_index(&mut self, index: usize) {
+        self.creature_index = index;
+        self.update_state();
     }
-}
 
-pub trait OneHotEncodedAction {
-    fn into_action(self, state: &CreatureState) -> CreatureAction;
+    fn update_state(&mut self) {
+        let species = &self.world.species[self.species];
+        self.state = CreatureState::new(self.world, species, self.time, self.creature_index).into();
+    }
 }
 
-impl OneHotEncodedAction for [f32; 4] {
-    fn into_action(self, state: &CreatureState) -> CreatureAction {
-        let one_hot = self.map(|v| v.clamp(0.0, 1.0));
-
-        let mut largest_action = (0, 0);
-        let mut largest_confidence = 0.0;
-        for (i, confidence) in one_hot.into_iter().enumerate() {
-            if confidence > largest_confidence {
-                largest_action = CreatureAction::DIRECTIONS[i];
-                largest_confidence = confidence;
-            }
-        }
+impl<'a, R: Rules, S> Agent<S> for SpeciesAgent<'a, R, S>
+where
+    S: State<A = CreatureAction> + From<CreatureState<R>>,
+{
+    fn current_state(&self) -> &S {
+        &self.state
+    }
 
-        if largest_action == (0, 0) {
-            CreatureAction::DoNothing
-        } else {
-            match state.slice[Pos(3, 3) + largest_action] {
-                Tile::Empty => CreatureAction::Move(largest_action.0, largest_action.1),
-                Tile::OutOfBounds => CreatureAction::DoNothing,
-                _ => CreatureAction::Attack(largest_action.0, largest_action.1),
-            }
-        }
+    fn take_action(&mut self, action: &CreatureAction) {
+        self.world
+            .act(self.species, self.creature_index, *action, 0);
+        self.update_state();
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone)]
-pub struct CreatureState {
-    /// 7x7 grid centered on creature.
-    slice: Grid<7, 7>,
-    /// Amount of food that the creature has.
-    food: isize,
-    /// How much time is left until the moon ends. (and it has to eat)
-    time: usize,
-}
+impl SimConfig {
+    pub fn create_controllers<R: Rules>(&self) -> Vec<Controller<R>> {
+        let mut controllers = Vec::new();
 
-impl CreatureState {
-    pub fn new(species: &Species, time: usize, index: usize) -> Self {
-        Self {
-            slice: species.get_view_slice(index),
-            food: species.get_food(index),
-            time,
+        for species in self.species {
+            controllers.push(Controller::new(*species));
         }
+
+        controllers
     }
 }
 
-impl State for CreatureState {
-    type A = CreatureAction;
+pub type SpeciesModel<R = DefaultRules> = Dqn<R>;
 
-    fn reward(&self) -> f64 {
-        (2 - self.food) as f64 * -(self.time as f64) * 1000.0
+impl<R: Rules> Policy<R> for SpeciesModel<R> {
+    /// Picks the legal action and the signal with the highest expected value.
+    fn choose_action(&mut self, state: &CreatureState<R>) -> (CreatureAction, u8) {
+        best_decision(state, &self.expected_value(state))
     }
 
-    fn actions(&self) -> Vec<Self::A> {
-        let mut actions = Vec::new();
-
-        for direction in CreatureAction::DIRECTIONS {
-            match self.slice[Pos(3, 3) + direction] {
-                Tile::Empty => {
-                    actions.push(CreatureAction::Move(direction.0, direction.1));
-                    if self.food > 0 {
-                        // actions.push(CreatureAction::BuildWall(direction.0, direction.1));
-                    }
-                }
-                Tile::Bush(true) | Tile::Creature { .. } | Tile::Wall { .. } => {
-                    actions.push(CreatureAction::Attack(direction.0, direction.1));
-                }
-                _ => (),
-            }
-        }
-
-        if actions.len() == 0 {
-            actions.push(CreatureAction::DoNothing);
-        }
-
-        actions
+    /// Runs the network once on all members, instead of once per member. Their states are only
+    /// built to list their legal actions.
+    fn choose_actions(&mut self, world: &World<R>, id: usize) -> Vec<(CreatureAction, u8)> {
+        let species = &world.species[id];
+        let time = world.moon_step();
+
+        self.species_values(world, species, time)
+            .chunks_exact(self.outputs())
+            .enumerate()
+            .map(|(index, values)| {
+                best_decision(&CreatureState::new(world, species, time, index), values)
+            })
+            .collect()
     }
 }
 
-impl Into<[f32; 100]> for CreatureState {
-    fn into(self) -> [f32; 100] {
-        let mut vec = Vec::new();
+/// Best action of `state` and best signal by `values`, which are laid out like
+/// [`Dqn::expected_value`].
+fn best_decision<R: Rules>(state: &CreatureState<R>, values: &[f32]) -> (CreatureAction, u8) {
+    let (actions, signals) = values.split_at(CreatureAction::NUM_ACTIONS);
+    let signals = &signals[..signals.len().min(state.num_signals as usize + 1)];
 
-        for tile in self.slice.arr().iter().flatten() {
-            vec.extend_from_slice(&Into::<[f32; 2]>::into(*tile));
-        }
+    (best_action(state, actions), dqn::best_index(signals) as u8)
+}
 
-        vec.push(self.food as f32);
-        vec.push(self.time as f32);
+/// Legal action of `state` with the highest of `values`, which are indexed by
+/// [`CreatureAction::index`].
+fn best_action<R: Rules>(state: &CreatureState<R>, values: &[f32]) -> CreatureAction {
+    let value = |action: &CreatureAction| action.index().map_or(f32::MIN, |i| values[i]);
 
-        vec.try_into().expect("too many tiles in grid")
-    }
+    R::actions(state)
+        .into_iter()
+        .max_by(|a, b| value(a).total_cmp(&value(b)))
+        .unwrap_or(CreatureAction::DoNothing)
 }
 
-pub struct SpeciesAgent<'a> {
-    state: CreatureState,
-    species: &'a Species,
-    pub time: usize,
-    creature_index: usize,
-    pub iters: usize,
+/// Controls a species, either by learning as it goes or with a fixed policy.
+pub enum Controller<R: Rules = DefaultRules> {
+    Dqn(Box<SpeciesModel<R>>),
+    Tabular(TabularModel<R>),
+    Fixed(Box<dyn Policy<R>>),
 }
 
-impl<'a> SpeciesAgent<'a> {
-    pub fn new(species: &'a Species) -> Self {
-        Self {
-            state: CreatureState::new(&species, 0, 0),
-            species,
-            time: 0,
-            creature_index: 0,
-            iters: 0,
+impl<R: Rules> Controller<R> {
+    /// Creates the controller of the policy of `species`.
+    pub fn new(species: SpeciesConfig) -> Self {
+        match species.policy {
+            PolicyKind::Dqn(config) => Controller::Dqn(Box::new(Dqn::new(config, species.signals))),
+            PolicyKind::Tabular => Controller::Tabular(TabularModel::new()),
+            PolicyKind::Random => Controller::Fixed(Box::new(RandomPolicy)),
+            PolicyKind::Greedy => Controller::Fixed(Box::new(GreedyPolicy)),
         }
     }
-
-    pub fn increment_index(&mut self) {
-        self.creature_index += 1;
-        self.state = CreatureState::new(&self.species, self.time, self.creature_index);
-    }
-
-    pub fn reset_index(&mut self) {
-        self.creature_index = 0;
-        self.state = CreatureState::new(&self.species, self.time, self.creature_index);
-    }
 }
 
-impl<'a> Agent<CreatureState> for SpeciesAgent<'a> {
-    fn current_state(&self) -> &CreatureState {
-        &self.state
+impl<R: Rules> Policy<R> for Controller<R> {
+    fn choose_action(&mut self, state: &CreatureState<R>) -> (CreatureAction, u8) {
+        match self {
+            Controller::Dqn(model) => model.choose_action(state),
+            Controller::Tabular(model) => model.choose_action(state),
+            Controller::Fixed(policy) => policy.choose_action(state),
+        }
     }
 
-    fn take_action(&mut self, action: &CreatureAction) {
-        self.species.handle_action(*action, self.creature_index);
-        if self.creature_index < self.iters - 1 {
-            self.increment_index();
+    fn choose_actions(&mut self, world: &World<R>, id: usize) -> Vec<(CreatureAction, u8)> {
+        match self {
+            Controller::Dqn(model) => model.choose_actions(world, id),
+            Controller::Tabular(model) => model.choose_actions(world, id),
+            Controller::Fixed(policy) => policy.choose_actions(world, id),
         }
     }
 }
 
-impl SimConfig {
-    pub fn create_dqn_models(&self) -> Vec<SpeciesModel> {
-        let mut models = Vec::new();
+/// Whether a creature that ended up in `next_state` will starve at the end of the step. The end of
+/// a moon isn't terminal, since creatures that survive it live on into the next one.
+fn is_done<R: Rules>(next_state: &CreatureState<R>) -> bool {
+    next_state.food < 0
+}
 
-        for _ in self.species {
-            models.push(Default::default());
-        }
+/// Trains `model` on as many batches sampled from `replay` as the `new` experiences just added to
+/// it fill, and updates the priorities of the sampled experiences.
+fn train_from_replay<R: Rules>(model: &mut SpeciesModel<R>, replay: &mut ReplayBuffer, new: usize) {
+    let batch_size = model.config.batch_size;
+
+    for _ in 0..new.div_ceil(batch_size) {
+        let indices = replay.sample(batch_size);
+        let weights = replay.weights(&indices);
+        let batch: Vec<&Experience> = indices.iter().map(|i| replay.get(*i)).collect();
 
-        models
+        let errors = model.train(&batch, &weights);
+        replay.update_priorities(&indices, &errors);
     }
 }
 
-pub type SpeciesModel = DQNAgentTrainer<CreatureState, 100, 4, 128>;
+/// Lets every living creature take one action, training the species that are learning. `time` is
+/// the number of steps since the start of the moon. DQN species store what they did in their
+/// buffer in `replays`, which has one per controller, and train on batches sampled from it.
+pub fn step_species<R: Rules>(
+    world: &mut World<R>,
+    controllers: &mut [Controller<R>],
+    replays: &mut [ReplayBuffer],
+    time: usize,
+) {
+    assert_eq!(
+        controllers.len(),
+        replays.len(),
+        "Need one replay buffer per controller."
+    );
+
+    for (species, controller) in controllers.iter_mut().enumerate() {
+        let iterations = world.species[species].members.len();
+        if iterations == 0 {
+            continue;
+        }
+
+        match controller {
+            Controller::Dqn(model) => {
+                let replay = &mut replays[species];
+                for index in 0..iterations {
+                    let state = CreatureState::new(world, &world.species[species], time, index);
+                    let (action, signal) = RandomPolicy.choose_action(&state);
+                    world.act(species, index, action, signal);
+
+                    let next_state =
+                        CreatureState::new(world, &world.species[species], time, index);
+                    let done = is_done(&next_state);
+                    replay.push(Experience::new(
+                        &model.config.observation,
+                        &state,
+                        (action, signal),
+                        &next_state,
+                        done,
+                    ));
+                }
 
-pub fn train_moons(world: &mut World, models: &mut Vec<SpeciesModel>, num_moons: usize) {
-    let mut species_data = Vec::new();
-    for (species, model) in world.species.iter().zip(models.iter_mut()) {
-        species_data.push((model, SpeciesAgent::new(&species), species));
+                train_from_replay(model, replay, iterations);
+            }
+            Controller::Tabular(trainer) => {
+                let mut agent = SpeciesAgent::<R, CompactState<R>>::new(world, species);
+                agent.time = time;
+
+                for index in 0..iterations {
+                    agent.set_index(index);
+                    trainer.train(
+                        &mut agent,
+                        &QLearning::new(tabular::ALPHA, tabular::GAMMA, 0.0),
+                        &mut FixedIterations::new(1),
+                        &RandomExploration::new(),
+                    );
+                }
+            }
+            Controller::Fixed(policy) => {
+                for index in 0..iterations {
+                    let state = CreatureState::new(world, &world.species[species], time, index);
+                    let (action, signal) = policy.choose_action(&state);
+                    world.act(species, index, action, signal);
+                }
+            }
+        }
     }
+}
 
+/// Trains `controllers` for up to `num_moons` moons of `world`, stopping early if every creature
+/// died. Returns the number of moons played.
+pub fn train_moons<R: Rules>(
+    world: &mut World<R>,
+    controllers: &mut [Controller<R>],
+    replays: &mut [ReplayBuffer],
+    num_moons: usize,
+) -> usize {
     for moon in 0..num_moons {
         for step in 0..world.config.moon_len {
-            for (trainer, agent, species) in &mut species_data {
-                let iterations = species.members.borrow().len();
-                if iterations == 0 {
-                    continue;
-                }
+            step_species(world, controllers, replays, step);
+            world.end_step();
+        }
 
-                agent.reset_index();
-                agent.time = step;
-                agent.iters = iterations;
+        if world.is_extinct() {
+            return moon + 1;
+        }
+    }
 
-                trainer.train(
-                    agent,
-                    &mut FixedIterations::new(iterations as u32),
-                    &RandomExploration::new(),
-                );
-            }
+    num_moons
+}
 
-            world.finish_step();
-        }
+/// Trains new controllers for `config` on `num_iters` fresh worlds, one after the other, each for
+/// up to `num_moons` moons.
+pub fn train_iters<R: Rules>(
+    config: SimConfig,
+    num_iters: usize,
+    num_moons: usize,
+) -> Vec<Controller<R>> {
+    let mut controllers = config.create_controllers();
+    let mut replays = vec![ReplayBuffer::new(ReplayConfig::DEFAULT); controllers.len()];
+
+    for _ in 0..num_iters {
+        let mut world = World::with_rules(config);
+        train_moons(&mut world, &mut controllers, &mut replays, num_moons);
+    }
 
-        world.finish_moon();
-        println!("  Moon {}/{num_moons}", moon + 1);
+    controllers
+}
 
-        if !world.species.iter().any(|s| s.members.borrow().len() > 0) {
-            println!("  Finished due to extinction.");
-            break;
+#[cfg(test)]
+mod tests {
+    use super::{
+        best_action, best_decision,
+        replay::{Experience, ReplayBuffer, ReplayConfig},
+        step_species, CompactState, Controller, SpeciesModel, TabularModel,
+    };
+    use crate::{
+        policy::{DqnConfig, RandomPolicy},
+        world::{
+            creature::{CreatureAction, CreatureState},
+            grid::{Grid, Pos},
+            observation::{ObservationEncoder, TILE_CHANNELS},
+            rules::{DefaultRules, Rules},
+            structure::{BuildConfig, StructureConfig},
+            tests::{world_with, CONFIG},
+            World,
+        },
+    };
+
+    #[test]
+    fn batched_values_match_single_values() {
+        let world = World::new(CONFIG);
+        let mut model = SpeciesModel::default();
+        let species = &world.species[0];
+        let states: Vec<CreatureState> = (0..species.members.len())
+            .map(|index| CreatureState::new(&world, species, 0, index))
+            .collect();
+
+        let batched = model.expected_values(&states);
+        assert_eq!(batched.len(), states.len() * model.outputs());
+        assert_eq!(model.species_values(&world, species, 0), batched);
+
+        for (state, values) in states.iter().zip(batched.chunks(model.outputs())) {
+            for (single, batched) in model.expected_value(state).iter().zip(values) {
+                assert!((single - batched).abs() < 1e-4, "{single} != {batched}");
+            }
         }
+        assert!(model.expected_values(&[]).is_empty());
     }
-}
 
-pub fn train_iters(config: SimConfig, num_iters: usize, num_moons: usize) -> Vec<SpeciesModel> {
-    let mut models = config.create_dqn_models();
+    #[test]
+    fn dqn_picks_the_legal_action_it_values_most() {
+        let world = World::new(CONFIG);
+        let species = &world.species[0];
+        let mut state = CreatureState::new(&world, species, 0, 0);
+
+        let mut slice = Grid::empty();
+        slice[Pos(3, 3)] = state.slice[Pos(3, 3)];
+        state.slice = slice;
+        state.food = 3;
+        state.build = BuildConfig {
+            wall: StructureConfig { cost: 1, hp: 2 },
+            nest: StructureConfig { cost: 1, hp: 2 },
+            trap: StructureConfig { cost: 1, hp: 2 },
+            ..BuildConfig::DISABLED
+        };
+        let value = |action: CreatureAction| action.index().expect("Action has no index.");
+        let mut values = [0.0; CreatureAction::NUM_ACTIONS];
+        values[value(CreatureAction::BuildTrap(0, -1))] = 1.0;
+        values[value(CreatureAction::PickUp(0, -1))] = 2.0;
+
+        assert!(!DefaultRules::actions(&state).contains(&CreatureAction::PickUp(0, -1)));
+        assert_eq!(
+            best_action(&state, &values),
+            CreatureAction::BuildTrap(0, -1)
+        );
+    }
 
-    for i in 0..num_iters {
-        let mut world = World::new(config);
-        let now = Instant::now();
+    #[test]
+    fn dqn_sends_the_best_signal_it_can() {
+        let world = World::new(CONFIG);
+        let mut state = CreatureState::new(&world, &world.species[0], 0, 0);
+        state.num_signals = 1;
 
-        train_moons(&mut world, &mut models, num_moons);
+        let mut values = vec![0.0; CreatureAction::NUM_ACTIONS];
+        values.extend([0.1, 0.5, 0.9]);
+        let (_, signal) = best_decision(&state, &values);
+        assert_eq!(signal, 1);
+    }
 
-        println!(
-            "# Epoch {}/{num_iters} completed in {} seconds.\n",
-            i + 1,
-            now.elapsed().as_secs() as f64
-                + (now.elapsed().subsec_millis() as f64 / 100.0).round() / 10.0,
-        )
+    #[test]
+    fn dqn_trains_on_a_smaller_view() {
+        let world = World::new(CONFIG);
+        let species = &world.species[0];
+        let mut model = SpeciesModel::new(
+            DqnConfig {
+                hidden_size: 16,
+                observation: ObservationEncoder {
+                    radius: 1,
+                    memory: 0,
+                    ..ObservationEncoder::DEFAULT
+                },
+                ..DqnConfig::DEFAULT
+            },
+            0,
+        );
+
+        let state = CreatureState::new(&world, species, 0, 0);
+        let next_state = CreatureState::new(&world, species, 1, 0);
+        let experience = Experience::new(
+            &model.config.observation,
+            &state,
+            (CreatureAction::Move(1, 0), 0),
+            &next_state,
+            false,
+        );
+        assert_eq!(experience.state.len(), 3 * 3 * TILE_CHANNELS + 7);
+
+        assert!(model.train(&[&experience], &[1.0])[0].is_finite());
+        assert!(model.expected_value(&state).iter().all(|v| v.is_finite()));
     }
 
-    models
+    #[test]
+    fn tabular_learns_from_where_each_creature_ended_up() {
+        // A starving creature and a fed one, too far apart to see each other. Moving is all the
+        // starving one can do, and it stays starving wherever it goes.
+        let creatures = [(0, Pos(5, 5), -1), (0, Pos(15, 15), 2)];
+        let time = 5;
+        let mut controllers = vec![
+            Controller::Tabular(TabularModel::new()),
+            Controller::Fixed(Box::new(RandomPolicy)),
+        ];
+        let mut replays = vec![ReplayBuffer::new(ReplayConfig::DEFAULT); 2];
+
+        let world = world_with(CONFIG, &creatures);
+        let start = CompactState::from(CreatureState::new(&world, &world.species[0], time, 0));
+        assert!(DefaultRules::reward(start.state()) < 0.0);
+
+        // rurel only starts updating a value from its second visit, so try each move a few times.
+        for _ in 0..60 {
+            let mut world = world_with(CONFIG, &creatures);
+            step_species(&mut world, &mut controllers, &mut replays, time);
+        }
+
+        let Controller::Tabular(model) = &controllers[0] else {
+            unreachable!();
+        };
+        let values = model
+            .expected_values(&start)
+            .expect("Start was never learned.");
+        assert_eq!(values.len(), 4);
+        assert!(values.values().all(|value| *value < 0.0), "{values:?}");
+    }
 }
@@ -0,0 +1,186 @@
+//! Saving and loading DQN models, together with their replay buffers, to continue training later.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use super::{
+    replay::{invalid_data, read_floats, read_u32, read_u64, read_u8, write_floats, ReplayBuffer},
+    SpeciesModel,
+};
+use crate::{
+    policy::DqnConfig,
+    world::{
+        observation::{ObservationEncoder, VIEW_RADIUS},
+        rules::Rules,
+    },
+};
+
+/// Start of every saved model, followed by a format version.
+const MAGIC: &[u8; 4] = b"DQNM";
+const VERSION: u32 = 1;
+
+/// File names within a checkpoint directory.
+const MODEL_FILE: &str = "model.bin";
+const REPLAY_FILE: &str = "replay.bin";
+
+/// Writes the config and weights of `model` to `path`.
+pub fn save_model<R: Rules>(model: &SpeciesModel<R>, path: &Path) -> io::Result<()> {
+    let config = model.config;
+    let observation = config.observation;
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(config.hidden_size as u64).to_le_bytes())?;
+    write_floats(&mut writer, &[config.learning_rate, config.gamma])?;
+    writer.write_all(&(config.batch_size as u64).to_le_bytes())?;
+    writer.write_all(&(config.steps_per_batch as u64).to_le_bytes())?;
+    writer.write_all(&(config.target_sync as u64).to_le_bytes())?;
+    writer.write_all(&[config.double as u8, model.signals])?;
+    writer.write_all(&(observation.radius as u64).to_le_bytes())?;
+    writer.write_all(&(observation.memory as u64).to_le_bytes())?;
+    write_floats(&mut writer, &[observation.max_food, observation.max_signal])?;
+
+    for parameter in model.parameters() {
+        writer.write_all(&(parameter.len() as u64).to_le_bytes())?;
+        write_floats(&mut writer, &parameter)?;
+    }
+
+    writer.flush()
+}
+
+/// Reads a model written by [`save_model`], with the config it was saved with.
+pub fn load_model<R: Rules>(path: &Path) -> io::Result<SpeciesModel<R>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC || read_u32(&mut reader)? != VERSION {
+        return Err(invalid_data("Not a model, or an unsupported version."));
+    }
+
+    let hidden_size = read_u64(&mut reader)? as usize;
+    let mut rates = [0.0; 2];
+    read_floats(&mut reader, &mut rates)?;
+    let batch_size = read_u64(&mut reader)? as usize;
+    let steps_per_batch = read_u64(&mut reader)? as usize;
+    let target_sync = read_u64(&mut reader)? as usize;
+    let double = read_u8(&mut reader)? != 0;
+    let signals = read_u8(&mut reader)?;
+    let radius = read_u64(&mut reader)? as usize;
+    let memory = read_u64(&mut reader)? as usize;
+    let mut scales = [0.0; 2];
+    read_floats(&mut reader, &mut scales)?;
+    if hidden_size == 0 || batch_size == 0 || target_sync == 0 || radius > VIEW_RADIUS {
+        return Err(invalid_data("Model has an invalid config."));
+    }
+
+    let mut model = SpeciesModel::new(
+        DqnConfig {
+            hidden_size,
+            learning_rate: rates[0],
+            gamma: rates[1],
+            batch_size,
+            steps_per_batch,
+            target_sync,
+            double,
+            observation: ObservationEncoder {
+                radius,
+                max_food: scales[0],
+                memory,
+                max_signal: scales[1],
+            },
+        },
+        signals,
+    );
+    let mut parameters = model.parameters();
+    for parameter in &mut parameters {
+        if read_u64(&mut reader)? != parameter.len() as u64 {
+            return Err(invalid_data("Model has a different shape."));
+        }
+        read_floats(&mut reader, parameter)?;
+    }
+    model.set_parameters(&parameters);
+
+    Ok(model)
+}
+
+/// Saves `model` and `replay` into the directory `dir`, creating it if needed.
+pub fn save<R: Rules>(
+    dir: &Path,
+    model: &SpeciesModel<R>,
+    replay: &ReplayBuffer,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    save_model(model, &dir.join(MODEL_FILE))?;
+    replay.save(&dir.join(REPLAY_FILE))
+}
+
+/// Loads a model and replay buffer saved with [`save`].
+pub fn load<R: Rules>(dir: &Path) -> io::Result<(SpeciesModel<R>, ReplayBuffer)> {
+    let model = load_model(&dir.join(MODEL_FILE))?;
+    let replay = ReplayBuffer::load(&dir.join(REPLAY_FILE))?;
+    if replay
+        .observation_size()
+        .is_some_and(|size| size != model.config.observation.size())
+    {
+        return Err(invalid_data(
+            "Replay buffer was saved with a different observation size than the model.",
+        ));
+    }
+
+    Ok((model, replay))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::{load, save};
+    use crate::{
+        policy::DqnConfig,
+        train::{
+            replay::{ReplayBuffer, ReplayConfig},
+            SpeciesModel,
+        },
+        world::{observation::ObservationEncoder, rules::DefaultRules},
+    };
+
+    #[test]
+    fn saved_checkpoint_loads_unchanged() {
+        let model = SpeciesModel::<DefaultRules>::new(
+            DqnConfig {
+                hidden_size: 16,
+                double: true,
+                observation: ObservationEncoder {
+                    radius: 2,
+                    memory: 1,
+                    ..ObservationEncoder::DEFAULT
+                },
+                ..DqnConfig::DEFAULT
+            },
+            2,
+        );
+        let replay = ReplayBuffer::new(ReplayConfig::DEFAULT);
+        let dir = env::temp_dir().join(format!("checkpoint-test-{}", std::process::id()));
+
+        save(&dir, &model, &replay).expect("Failed to save checkpoint.");
+        let (loaded, loaded_replay) =
+            load::<DefaultRules>(&dir).expect("Failed to load checkpoint.");
+        fs::remove_dir_all(&dir).ok();
+
+        let observations: Vec<f32> = (0..model.config.observation.size() * 2)
+            .map(|i| (i % 7) as f32 / 7.0)
+            .collect();
+        assert_eq!(
+            loaded.q_values(&observations),
+            model.q_values(&observations)
+        );
+        assert_eq!(loaded.config, model.config);
+        assert_eq!(loaded.signals, model.signals);
+        assert_eq!(loaded_replay, replay);
+    }
+}
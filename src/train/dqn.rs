@@ -0,0 +1,486 @@
+//! Deep Q-network that estimates how much reward each action and each signal lead to, with the
+//! knobs of [`DqnConfig`].
+
+use std::marker::PhantomData;
+
+use dfdx::prelude::*;
+
+use super::replay::Experience;
+use crate::{
+    policy::DqnConfig,
+    world::{
+        creature::{CreatureAction, CreatureState},
+        rules::{DefaultRules, Rules},
+        species::Species,
+        World,
+    },
+};
+
+/// Nesterov momentum of the optimizer.
+const MOMENTUM: f32 = 0.9;
+
+type Matrix<T = NoneTape> = Tensor<(usize, usize), f32, Cpu, T>;
+type Vector = Tensor<(usize,), f32, Cpu>;
+
+#[derive(Clone)]
+struct Layer {
+    /// Shape (inputs, outputs).
+    weight: Matrix,
+    bias: Vector,
+}
+
+impl Layer {
+    /// Draws weights and biases uniformly from ±1/sqrt(`inputs`), like dfdx does.
+    fn new(dev: &Cpu, inputs: usize, outputs: usize) -> Self {
+        let bound = 1.0 / (inputs as f32).sqrt();
+        let sample = |n: usize| -> Vec<f32> {
+            (0..n)
+                .map(|_| (rand::random::<f32>() * 2.0 - 1.0) * bound)
+                .collect()
+        };
+
+        Self {
+            weight: dev.tensor_from_vec(sample(inputs * outputs), (inputs, outputs)),
+            bias: dev.tensor_from_vec(sample(outputs), (outputs,)),
+        }
+    }
+
+    fn forward<T: Tape<f32, Cpu> + Merge<T>>(&self, x: Matrix<T>) -> Matrix<T> {
+        let shape = (x.shape().0, self.bias.shape().0);
+        x.matmul(self.weight.retaped::<T>())
+            + self
+                .bias
+                .retaped::<T>()
+                .broadcast_like::<_, Axis<0>>(&shape)
+    }
+}
+
+/// Two hidden layers with ReLU activations, followed by a linear output layer.
+#[derive(Clone)]
+struct Network {
+    layers: [Layer; 3],
+}
+
+impl Network {
+    fn new(dev: &Cpu, inputs: usize, hidden_size: usize, outputs: usize) -> Self {
+        Self {
+            layers: [
+                Layer::new(dev, inputs, hidden_size),
+                Layer::new(dev, hidden_size, hidden_size),
+                Layer::new(dev, hidden_size, outputs),
+            ],
+        }
+    }
+
+    fn forward<T: Tape<f32, Cpu> + Merge<T>>(&self, x: Matrix<T>) -> Matrix<T> {
+        let x = self.layers[0].forward(x).relu();
+        let x = self.layers[1].forward(x).relu();
+        self.layers[2].forward(x)
+    }
+}
+
+pub struct Dqn<R: Rules = DefaultRules> {
+    pub config: DqnConfig,
+    /// Number of signals the species can send. The network has an output for each of them, and
+    /// one for sending none, after the [`CreatureAction::NUM_ACTIONS`] action outputs.
+    pub signals: u8,
+    network: Network,
+    /// Copy of `network` from the last sync.
+    target: Network,
+    /// Momentum of each weight and bias, in the order of [`Self::parameters`].
+    velocity: Vec<Vec<f32>>,
+    /// Observations of the last batch that was evaluated, kept so its memory is reused.
+    observations: Vec<f32>,
+    /// Number of gradient steps taken so far.
+    steps: usize,
+    dev: Cpu,
+    rules: PhantomData<R>,
+}
+
+impl<R: Rules> Dqn<R> {
+    /// Creates a network for a species that can send `signals` distinct signals.
+    pub fn new(config: DqnConfig, signals: u8) -> Self {
+        assert!(config.hidden_size > 0, "DQN needs a hidden layer.");
+        assert!(config.batch_size > 0, "DQN batches can't be empty.");
+        assert!(
+            config.target_sync > 0,
+            "DQN target network has to be synced."
+        );
+
+        let dev = Cpu::default();
+        let outputs = CreatureAction::NUM_ACTIONS + signals as usize + 1;
+        let network = Network::new(&dev, config.observation.size(), config.hidden_size, outputs);
+        let mut dqn = Self {
+            config,
+            signals,
+            target: network.clone(),
+            network,
+            velocity: Vec::new(),
+            observations: Vec::new(),
+            steps: 0,
+            dev,
+            rules: PhantomData,
+        };
+        dqn.velocity = dqn
+            .parameters()
+            .iter()
+            .map(|p| vec![0.0; p.len()])
+            .collect();
+
+        dqn
+    }
+
+    /// Number of outputs of the network: an action of each [`CreatureAction::index`], then signals
+    /// from zero (none) to [`Self::signals`].
+    pub fn outputs(&self) -> usize {
+        CreatureAction::NUM_ACTIONS + self.signals as usize + 1
+    }
+
+    /// Expected value of each action for `state`, by [`CreatureAction::index`], followed by the
+    /// expected value of each signal. See [`Self::outputs`].
+    pub fn expected_value(&mut self, state: &CreatureState<R>) -> Vec<f32> {
+        self.expected_values(std::slice::from_ref(state))
+    }
+
+    /// Same as [`Self::expected_value`] for each of `states`, one after the other, but in a
+    /// single forward pass.
+    pub fn expected_values(&mut self, states: &[CreatureState<R>]) -> Vec<f32> {
+        let encoder = self.config.observation;
+        let mut observations = std::mem::take(&mut self.observations);
+        observations.resize(states.len() * encoder.size(), 0.0);
+        for (state, out) in states
+            .iter()
+            .zip(observations.chunks_exact_mut(encoder.size()))
+        {
+            encoder.encode_into(state, out);
+        }
+
+        let values = self.q_values(&observations);
+        self.observations = observations;
+        values
+    }
+
+    /// Same as [`Self::expected_values`] for the states of every member of `species` at `time`,
+    /// but encoded straight from `world`.
+    pub fn species_values(&mut self, world: &World<R>, species: &Species, time: usize) -> Vec<f32> {
+        let encoder = self.config.observation;
+        let mut observations = std::mem::take(&mut self.observations);
+        observations.resize(species.members.len() * encoder.size(), 0.0);
+        encoder.encode_species(world, species, time, &mut observations);
+
+        let values = self.q_values(&observations);
+        self.observations = observations;
+        values
+    }
+
+    /// Expected value of each output for each of `observations`, which are laid out one after the
+    /// other.
+    pub fn q_values(&self, observations: &[f32]) -> Vec<f32> {
+        if observations.is_empty() {
+            return Vec::new();
+        }
+
+        self.network
+            .forward(self.batch(observations.to_vec()))
+            .nans_to(0.0)
+            .as_vec()
+    }
+
+    /// Takes [`DqnConfig::steps_per_batch`] gradient steps on `batch`, with the loss of each
+    /// experience scaled by its weight in `weights`, and returns the TD error of each experience's
+    /// action afterwards: how far its expected value is from its reward plus the discounted value
+    /// of the next state. Its signal is trained towards the same target.
+    pub fn train(&mut self, batch: &[&Experience], weights: &[f32]) -> Vec<f32> {
+        assert_eq!(
+            batch.len(),
+            weights.len(),
+            "Need one weight per experience."
+        );
+        if batch.is_empty() {
+            return Vec::new();
+        }
+
+        let columns: Vec<usize> = batch
+            .iter()
+            .flat_map(|e| {
+                let signal = e.signal.min(self.signals) as usize;
+                [e.action as usize, CreatureAction::NUM_ACTIONS + signal]
+            })
+            .collect();
+
+        let states = self.batch(batch.iter().flat_map(|e| e.state.iter().copied()).collect());
+        let next_states = self.batch(
+            batch
+                .iter()
+                .flat_map(|e| e.next_state.iter().copied())
+                .collect(),
+        );
+        let weights = self
+            .dev
+            .tensor_from_vec(weights.to_vec(), (batch.len(),))
+            .broadcast_like::<_, Axis<1>>(&(batch.len(), 2));
+
+        for _ in 0..self.config.steps_per_batch {
+            let targets = self.targets(batch, &next_states);
+            let targets = self.dev.tensor_from_vec(targets, (batch.len(), 2));
+            let columns = self.dev.tensor_from_vec(columns.clone(), (batch.len(), 2));
+
+            let q_values = self.network.forward(states.leaky_trace());
+            let loss =
+                (q_values.gather(columns).huber_error(targets, 1.0) * weights.clone()).mean();
+            let grads = loss.backward();
+            self.step(&grads);
+        }
+
+        let targets = self.targets(batch, &next_states);
+        let q_values = self.network.forward(states).nans_to(0.0).as_vec();
+        (0..batch.len())
+            .map(|i| targets[i * 2] - q_values[i * self.outputs() + columns[i * 2]])
+            .collect()
+    }
+
+    /// Weights and biases of each layer, in order. Weights are laid out as (inputs, outputs).
+    pub fn parameters(&self) -> Vec<Vec<f32>> {
+        self.network
+            .layers
+            .iter()
+            .flat_map(|layer| [layer.weight.as_vec(), layer.bias.as_vec()])
+            .collect()
+    }
+
+    /// Replaces the weights and biases with `parameters`, laid out like [`Self::parameters`],
+    /// syncs the target network and forgets the momentum of the old ones.
+    pub fn set_parameters(&mut self, parameters: &[Vec<f32>]) {
+        let lens: Vec<usize> = self.parameters().iter().map(Vec::len).collect();
+        assert!(
+            parameters.iter().map(Vec::len).eq(lens),
+            "Parameters have the wrong shape."
+        );
+
+        for (layer, values) in self.network.layers.iter_mut().zip(parameters.chunks(2)) {
+            layer.weight.copy_from(&values[0]);
+            layer.bias.copy_from(&values[1]);
+        }
+        self.target = self.network.clone();
+        self.velocity
+            .iter_mut()
+            .for_each(|velocity| velocity.fill(0.0));
+    }
+
+    /// Turns observations laid out one after the other into a normalized batch.
+    fn batch(&self, observations: Vec<f32>) -> Matrix {
+        let size = self.config.observation.size();
+        let len = observations.len() / size;
+        assert_eq!(
+            len * size,
+            observations.len(),
+            "Observations have the wrong size."
+        );
+
+        self.dev
+            .tensor_from_vec(observations, (len, size))
+            .normalize::<Axis<1>>(0.001)
+    }
+
+    /// Reward of each experience plus the discounted value of its next state, once for the best
+    /// next action and once for the best next signal.
+    fn targets(&self, batch: &[&Experience], next_states: &Matrix) -> Vec<f32> {
+        let target = self
+            .target
+            .forward(next_states.clone())
+            .nans_to(0.0)
+            .as_vec();
+        let online = if self.config.double {
+            self.network
+                .forward(next_states.clone())
+                .nans_to(0.0)
+                .as_vec()
+        } else {
+            target.clone()
+        };
+
+        let outputs = self.outputs();
+        batch
+            .iter()
+            .enumerate()
+            .flat_map(|(i, experience)| {
+                let row = i * outputs;
+                let actions = row..row + CreatureAction::NUM_ACTIONS;
+                [actions.clone(), actions.end..row + outputs].map(|head| {
+                    if experience.done {
+                        return experience.reward;
+                    }
+
+                    let best = best_index(&online[head.clone()]);
+                    experience.reward + self.config.gamma * target[head][best]
+                })
+            })
+            .collect()
+    }
+
+    /// Applies one step of SGD with Nesterov momentum, and syncs the target network every
+    /// [`DqnConfig::target_sync`] steps.
+    fn step(&mut self, grads: &Gradients<f32, Cpu>) {
+        let learning_rate = self.config.learning_rate;
+        let mut velocity = self.velocity.iter_mut();
+
+        for layer in &mut self.network.layers {
+            let weight_grad = grads.get(&layer.weight).as_vec();
+            let bias_grad = grads.get(&layer.bias).as_vec();
+            update(
+                &mut layer.weight,
+                weight_grad,
+                velocity.next(),
+                learning_rate,
+            );
+            update(&mut layer.bias, bias_grad, velocity.next(), learning_rate);
+        }
+
+        self.steps += 1;
+        if self.steps.is_multiple_of(self.config.target_sync) {
+            self.target = self.network.clone();
+        }
+    }
+}
+
+impl<R: Rules> Default for Dqn<R> {
+    fn default() -> Self {
+        Self::new(DqnConfig::DEFAULT, 0)
+    }
+}
+
+fn update<S: Shape>(
+    parameter: &mut Tensor<S, f32, Cpu>,
+    grad: Vec<f32>,
+    velocity: Option<&mut Vec<f32>>,
+    learning_rate: f32,
+) {
+    let velocity = velocity.expect("Missing momentum for a parameter.");
+    let mut values = parameter.as_vec();
+
+    for ((value, grad), velocity) in values.iter_mut().zip(grad).zip(velocity) {
+        *velocity = MOMENTUM * *velocity + grad;
+        *value -= learning_rate * (grad + MOMENTUM * *velocity);
+    }
+    parameter.copy_from(&values);
+}
+
+/// Index of the largest of `values`, which is the best action or signal by expected value. Ties go
+/// to the lowest index.
+pub(super) fn best_index(values: &[f32]) -> usize {
+    (0..values.len())
+        .max_by(|a, b| values[*a].total_cmp(&values[*b]).then(b.cmp(a)))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dqn;
+    use crate::{
+        policy::DqnConfig,
+        train::replay::Experience,
+        world::{creature::CreatureAction, observation::ObservationEncoder, rules::DefaultRules},
+    };
+
+    #[test]
+    fn training_reduces_td_error() {
+        let mut model = Dqn::<DefaultRules>::new(
+            DqnConfig {
+                hidden_size: 16,
+                learning_rate: 1e-2,
+                steps_per_batch: 50,
+                target_sync: 5,
+                double: true,
+                ..DqnConfig::DEFAULT
+            },
+            0,
+        );
+        let experiences: Vec<Experience> = (0..8)
+            .map(|i| {
+                let mut state = vec![0.0; ObservationEncoder::DEFAULT.size()];
+                for (j, value) in state.iter_mut().enumerate() {
+                    *value = ((i + j) % 5) as f32;
+                }
+                Experience {
+                    state: state.into(),
+                    action: (i * 5 % CreatureAction::NUM_ACTIONS) as u8,
+                    signal: 0,
+                    reward: i as f32 / 8.0,
+                    next_state: vec![1.0; ObservationEncoder::DEFAULT.size()].into(),
+                    done: i % 2 == 0,
+                }
+            })
+            .collect();
+        let batch: Vec<&Experience> = experiences.iter().collect();
+        let weights = vec![1.0; batch.len()];
+        let error = |errors: Vec<f32>| errors.iter().map(|e| e * e).sum::<f32>();
+
+        let before = error(model.train(&batch, &weights));
+        let after = error(model.train(&batch, &weights));
+        assert!(after < before, "{after} >= {before}");
+    }
+
+    #[test]
+    fn setting_parameters_resets_momentum() {
+        let mut model = Dqn::<DefaultRules>::new(
+            DqnConfig {
+                hidden_size: 16,
+                ..DqnConfig::DEFAULT
+            },
+            0,
+        );
+        let experience = Experience {
+            state: vec![1.0; ObservationEncoder::DEFAULT.size()].into(),
+            action: 0,
+            signal: 0,
+            reward: 1.0,
+            next_state: vec![1.0; ObservationEncoder::DEFAULT.size()].into(),
+            done: true,
+        };
+        model.train(&[&experience], &[1.0]);
+        assert!(model.velocity.iter().flatten().any(|v| *v != 0.0));
+
+        let parameters = model.parameters();
+        model.set_parameters(&parameters);
+        assert!(model.velocity.iter().flatten().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn actions_and_signals_are_trained_towards_their_reward() {
+        let mut model = Dqn::<DefaultRules>::new(
+            DqnConfig {
+                hidden_size: 16,
+                learning_rate: 1e-2,
+                ..DqnConfig::DEFAULT
+            },
+            2,
+        );
+        assert_eq!(model.outputs(), CreatureAction::NUM_ACTIONS + 3);
+
+        let action = CreatureAction::BuildTrap(0, -1)
+            .index()
+            .expect("Action has no index.");
+        let signal = CreatureAction::NUM_ACTIONS + 2;
+        let experience = Experience {
+            state: vec![1.0; ObservationEncoder::DEFAULT.size()].into(),
+            action: action as u8,
+            signal: 2,
+            reward: 1.0,
+            next_state: vec![1.0; ObservationEncoder::DEFAULT.size()].into(),
+            done: true,
+        };
+        let observation = vec![1.0; ObservationEncoder::DEFAULT.size()];
+        let errors = |model: &Dqn| {
+            let values = model.q_values(&observation);
+            [(1.0 - values[action]).abs(), (1.0 - values[signal]).abs()]
+        };
+
+        let before = errors(&model);
+        model.train(&[&experience], &[1.0]);
+        let after = errors(&model);
+        assert!(
+            after[0] < before[0] && after[1] < before[1],
+            "{after:?} {before:?}"
+        );
+    }
+}
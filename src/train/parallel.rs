@@ -0,0 +1,226 @@
+//! Runs several worlds at once on their own threads, and trains the species models on everything
+//! that happened in them.
+
+use std::thread;
+
+use rurel::{
+    mdp::Agent,
+    strategy::{explore::ExplorationStrategy, learn::QLearning},
+};
+
+use super::{
+    is_done,
+    replay::{Experience, ReplayBuffer, ReplayConfig},
+    tabular,
+    terminate::FixedIterations,
+    train_from_replay, CompactState, Controller,
+};
+use crate::{
+    policy::{GreedyPolicy, Policy, PolicyKind, RandomPolicy},
+    world::{
+        creature::{CreatureAction, CreatureState},
+        rules::Rules,
+        SimConfig, World,
+    },
+};
+
+/// One action of one creature.
+#[derive(Clone)]
+pub struct Transition<R: Rules> {
+    pub species: usize,
+    pub state: CreatureState<R>,
+    pub action: CreatureAction,
+    /// Signal sent alongside `action`, zero for none.
+    pub signal: u8,
+    /// State of the creature right after it acted.
+    pub next_state: CreatureState<R>,
+    /// Whether the creature will starve at the end of the step.
+    pub done: bool,
+}
+
+/// Plays `num_moons` moons of `world`, and returns what every creature of a learning species did.
+///
+/// Learning species act randomly, like they explore in [`train_moons`](super::train_moons), so
+/// the rollout doesn't need their models. Other species follow their policy.
+pub fn rollout<R: Rules>(mut world: World<R>, num_moons: usize) -> Vec<Transition<R>> {
+    let mut policies: Vec<Box<dyn Policy<R>>> = world
+        .config
+        .species
+        .iter()
+        .map(|species| match species.policy {
+            PolicyKind::Greedy => Box::new(GreedyPolicy) as Box<dyn Policy<R>>,
+            _ => Box::new(RandomPolicy),
+        })
+        .collect();
+    let mut transitions = Vec::new();
+
+    for _ in 0..num_moons * world.config.moon_len {
+        let time = world.moon_step();
+
+        for (species, policy) in policies.iter_mut().enumerate() {
+            let learning = matches!(
+                world.config.species[species].policy,
+                PolicyKind::Dqn(_) | PolicyKind::Tabular
+            );
+
+            for index in 0..world.species[species].members.len() {
+                let state = CreatureState::new(&world, &world.species[species], time, index);
+                let (action, signal) = policy.choose_action(&state);
+                world.act(species, index, action, signal);
+
+                if learning {
+                    let next_state =
+                        CreatureState::new(&world, &world.species[species], time, index);
+                    transitions.push(Transition {
+                        species,
+                        state,
+                        action,
+                        signal,
+                        done: is_done(&next_state),
+                        next_state,
+                    });
+                }
+            }
+        }
+
+        world.end_step();
+        if world.is_extinct() {
+            break;
+        }
+    }
+
+    transitions
+}
+
+/// Trains each controller on the transitions of its species. Fixed policies are left alone.
+///
+/// DQN transitions are added to the species' replay buffer, and the model is trained on as many
+/// batches sampled from it as the new transitions fill.
+pub fn learn<R: Rules>(
+    controllers: &mut [Controller<R>],
+    replays: &mut [ReplayBuffer],
+    transitions: &[Transition<R>],
+) {
+    for (id, (controller, replay)) in controllers.iter_mut().zip(replays).enumerate() {
+        let transitions: Vec<&Transition<R>> =
+            transitions.iter().filter(|t| t.species == id).collect();
+
+        match controller {
+            Controller::Dqn(model) => {
+                for transition in &transitions {
+                    replay.push(Experience::new(
+                        &model.config.observation,
+                        &transition.state,
+                        (transition.action, transition.signal),
+                        &transition.next_state,
+                        transition.done,
+                    ));
+                }
+                train_from_replay(model, replay, transitions.len());
+            }
+            Controller::Tabular(trainer) => {
+                let learning = QLearning::new(tabular::ALPHA, tabular::GAMMA, 0.0);
+
+                for transition in transitions {
+                    let mut agent = Replay {
+                        state: transition.state.clone().into(),
+                        next_state: Some(transition.next_state.clone().into()),
+                    };
+
+                    trainer.train(
+                        &mut agent,
+                        &learning,
+                        &mut FixedIterations::new(1),
+                        &ReplayAction(transition.action),
+                    );
+                }
+            }
+            Controller::Fixed(_) => (),
+        }
+    }
+}
+
+/// Like [`train_iters`](super::train_iters), but every iteration plays `num_envs` worlds at once,
+/// each on its own thread, and trains on all of them afterwards.
+pub fn train_parallel<R: Rules>(
+    config: SimConfig,
+    num_iters: usize,
+    num_moons: usize,
+    num_envs: usize,
+) -> Vec<Controller<R>> {
+    let mut controllers = config.create_controllers();
+    let mut replays = vec![ReplayBuffer::new(ReplayConfig::DEFAULT); controllers.len()];
+
+    train_parallel_with(
+        config,
+        &mut controllers,
+        &mut replays,
+        num_iters,
+        num_moons,
+        num_envs,
+    );
+
+    controllers
+}
+
+/// Continues training `controllers` like [`train_parallel`], with a replay buffer per species.
+/// Use this to train from a [checkpoint](super::checkpoint), or to save one afterwards.
+pub fn train_parallel_with<R: Rules>(
+    config: SimConfig,
+    controllers: &mut [Controller<R>],
+    replays: &mut [ReplayBuffer],
+    num_iters: usize,
+    num_moons: usize,
+    num_envs: usize,
+) {
+    assert_eq!(
+        controllers.len(),
+        replays.len(),
+        "Need one replay buffer per controller."
+    );
+
+    for _ in 0..num_iters {
+        let worlds: Vec<World<R>> = (0..num_envs).map(|_| World::with_rules(config)).collect();
+
+        let transitions: Vec<Transition<R>> = thread::scope(|scope| {
+            let handles: Vec<_> = worlds
+                .into_iter()
+                .map(|world| scope.spawn(move || rollout(world, num_moons)))
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("Rollout thread panicked."))
+                .collect()
+        });
+        learn(controllers, replays, &transitions);
+    }
+}
+
+/// Agent that goes from a recorded state to the state that followed it, whatever the action.
+struct Replay<R: Rules> {
+    state: CompactState<R>,
+    next_state: Option<CompactState<R>>,
+}
+
+impl<R: Rules> Agent<CompactState<R>> for Replay<R> {
+    fn current_state(&self) -> &CompactState<R> {
+        &self.state
+    }
+
+    fn take_action(&mut self, _: &CreatureAction) {
+        if let Some(next_state) = self.next_state.take() {
+            self.state = next_state;
+        }
+    }
+}
+
+/// Exploration that always picks the recorded action.
+struct ReplayAction(CreatureAction);
+
+impl<R: Rules> ExplorationStrategy<CompactState<R>> for ReplayAction {
+    fn pick_action(&self, agent: &mut dyn Agent<CompactState<R>>) -> CreatureAction {
+        agent.take_action(&self.0);
+        self.0
+    }
+}
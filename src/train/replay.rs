@@ -0,0 +1,553 @@
+//! Experience replay for the DQN, so it learns from a mix of old and new transitions rather than
+//! only from the latest ones, in the order they happened.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::world::{
+    creature::{CreatureAction, CreatureState},
+    observation::ObservationEncoder,
+    rules::Rules,
+};
+
+/// Smallest priority a transition can have, so that every transition can still be sampled.
+const MIN_PRIORITY: f32 = 1e-3;
+
+/// Start of every saved replay buffer, followed by a format version.
+const MAGIC: &[u8; 4] = b"RPLY";
+const VERSION: u32 = 1;
+
+/// One transition, as the network sees it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Experience {
+    /// Observation of the state, made by the encoder of the network that learns from it.
+    pub state: Box<[f32]>,
+    /// [`CreatureAction::index`] of the action.
+    pub action: u8,
+    /// Signal sent alongside the action, zero for none.
+    pub signal: u8,
+    pub reward: f32,
+    /// Observation of the next state, the same size as `state`.
+    pub next_state: Box<[f32]>,
+    /// Whether the episode ended with this transition.
+    pub done: bool,
+}
+
+impl Experience {
+    /// Experience of a creature that took `action` and sent `signal` in `state` and ended up in
+    /// `next_state`, rewarded by [`Rules::reward`] of where it ended up. `done` is whether that
+    /// ended the episode. Both states are observed through `encoder`. `action` has to have an
+    /// [index](CreatureAction::index).
+    pub fn new<R: Rules>(
+        encoder: &ObservationEncoder,
+        state: &CreatureState<R>,
+        (action, signal): (CreatureAction, u8),
+        next_state: &CreatureState<R>,
+        done: bool,
+    ) -> Self {
+        Self {
+            state: encoder.encode(state).into(),
+            action: action
+                .index()
+                .expect("Action isn't aimed at one of the directions.") as u8,
+            signal,
+            reward: R::reward(next_state) as f32,
+            next_state: encoder.encode(next_state).into(),
+            done,
+        }
+    }
+}
+
+/// How transitions are picked from a [`ReplayBuffer`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Sampling {
+    /// Every stored transition is equally likely.
+    Uniform,
+    /// Transitions are picked with probability proportional to their priority to the power of
+    /// `alpha`, so ones the network predicts badly are revisited more often. `beta` is how much
+    /// [`ReplayBuffer::weights`] corrects for the bias this introduces, from 0 (not at all) to 1
+    /// (fully).
+    Prioritized { alpha: f32, beta: f32 },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ReplayConfig {
+    /// Number of transitions kept. Once full, the oldest ones are replaced first.
+    pub capacity: usize,
+    pub sampling: Sampling,
+}
+
+impl ReplayConfig {
+    pub const DEFAULT: Self = Self {
+        capacity: 5000,
+        sampling: Sampling::Prioritized {
+            alpha: 0.6,
+            beta: 0.4,
+        },
+    };
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReplayBuffer {
+    pub config: ReplayConfig,
+    experiences: Vec<Experience>,
+    /// Priority of each experience, in the same order.
+    priorities: Vec<f32>,
+    /// Priorities to the power of alpha, which experiences are sampled by.
+    tree: PriorityTree,
+    /// Index that the next experience replaces, once the buffer is full.
+    next: usize,
+    /// Highest priority so far, which new experiences start with so they get sampled at least
+    /// once.
+    max_priority: f32,
+}
+
+impl ReplayBuffer {
+    pub fn new(config: ReplayConfig) -> Self {
+        assert!(
+            config.capacity > 0,
+            "Replay buffer capacity has to be positive."
+        );
+
+        Self {
+            config,
+            experiences: Vec::new(),
+            priorities: Vec::new(),
+            tree: PriorityTree::new(config.capacity),
+            next: 0,
+            max_priority: 1.0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.experiences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.experiences.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> &Experience {
+        &self.experiences[index]
+    }
+
+    /// Length of the observations in the buffer, or `None` while it is empty.
+    pub fn observation_size(&self) -> Option<usize> {
+        self.experiences.first().map(|e| e.state.len())
+    }
+
+    /// Adds `experience`, replacing the oldest one if the buffer is full. Its observations have to
+    /// be as long as the ones already in the buffer.
+    pub fn push(&mut self, experience: Experience) {
+        assert!(
+            experience.next_state.len() == experience.state.len()
+                && self
+                    .observation_size()
+                    .is_none_or(|size| size == experience.state.len()),
+            "Experience has observations of the wrong size."
+        );
+
+        if self.experiences.len() < self.config.capacity {
+            self.experiences.push(experience);
+            self.priorities.push(0.0);
+        } else {
+            self.experiences[self.next] = experience;
+        }
+        self.set_priority(self.next, self.max_priority);
+        self.next = (self.next + 1) % self.config.capacity;
+    }
+
+    /// Exponent that priorities are raised to for sampling.
+    fn alpha(&self) -> f32 {
+        match self.config.sampling {
+            Sampling::Uniform => 1.0,
+            Sampling::Prioritized { alpha, .. } => alpha,
+        }
+    }
+
+    fn set_priority(&mut self, index: usize, priority: f32) {
+        self.priorities[index] = priority;
+        self.tree.set(index, priority.powf(self.alpha()));
+    }
+
+    /// Indices of `n` experiences, picked with replacement. Empty if the buffer is.
+    pub fn sample(&self, n: usize) -> Vec<usize> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        match self.config.sampling {
+            Sampling::Uniform => (0..n)
+                .map(|_| rand::random::<usize>() % self.len())
+                .collect(),
+            Sampling::Prioritized { .. } => (0..n)
+                .map(|_| {
+                    let target = rand::random::<f32>() * self.tree.total();
+                    self.tree.find(target).min(self.len() - 1)
+                })
+                .collect(),
+        }
+    }
+
+    /// Importance-sampling weights of the experiences at `indices`, scaled so the largest possible
+    /// weight is 1. Multiplying each experience's loss by its weight undoes the bias of
+    /// prioritized sampling. Always 1 for uniform sampling.
+    pub fn weights(&self, indices: &[usize]) -> Vec<f32> {
+        let Sampling::Prioritized { beta, .. } = self.config.sampling else {
+            return vec![1.0; indices.len()];
+        };
+
+        let total = self.tree.total();
+        let weight = |p: f32| (self.len() as f32 * p / total).powf(-beta);
+        let max_weight = weight(self.tree.min());
+
+        indices
+            .iter()
+            .map(|i| weight(self.tree.get(*i)) / max_weight)
+            .collect()
+    }
+
+    /// Sets the priorities of the experiences at `indices` from how far off the network's
+    /// prediction for each was (its TD error).
+    pub fn update_priorities(&mut self, indices: &[usize], errors: &[f32]) {
+        assert_eq!(indices.len(), errors.len(), "Need one error per index.");
+
+        for (index, error) in indices.iter().zip(errors) {
+            let priority = if error.is_finite() {
+                error.abs().max(MIN_PRIORITY)
+            } else {
+                self.max_priority
+            };
+            self.set_priority(*index, priority);
+            self.max_priority = self.max_priority.max(priority);
+        }
+    }
+
+    /// Writes the buffer, including its config and priorities, to `path`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.observation_size().unwrap_or(0) as u64).to_le_bytes())?;
+        writer.write_all(&(self.config.capacity as u64).to_le_bytes())?;
+        let (tag, alpha, beta) = match self.config.sampling {
+            Sampling::Uniform => (0u8, 0.0f32, 0.0f32),
+            Sampling::Prioritized { alpha, beta } => (1, alpha, beta),
+        };
+        writer.write_all(&[tag])?;
+        write_floats(&mut writer, &[alpha, beta, self.max_priority])?;
+        writer.write_all(&(self.next as u64).to_le_bytes())?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+
+        for (experience, priority) in self.experiences.iter().zip(&self.priorities) {
+            write_floats(&mut writer, &experience.state[..])?;
+            writer.write_all(&[experience.action, experience.signal])?;
+            write_floats(&mut writer, &[experience.reward])?;
+            write_floats(&mut writer, &experience.next_state[..])?;
+            writer.write_all(&[experience.done as u8])?;
+            write_floats(&mut writer, &[*priority])?;
+        }
+
+        writer.flush()
+    }
+
+    /// Reads a buffer written by [`Self::save`].
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut reader)? != VERSION {
+            return Err(invalid_data(
+                "Not a replay buffer, or an unsupported version.",
+            ));
+        }
+        let observation_size = read_u64(&mut reader)? as usize;
+        let capacity = read_u64(&mut reader)? as usize;
+        let sampling = match read_u8(&mut reader)? {
+            0 => {
+                read_floats(&mut reader, &mut [0.0; 2])?;
+                Sampling::Uniform
+            }
+            1 => {
+                let mut params = [0.0; 2];
+                read_floats(&mut reader, &mut params)?;
+                Sampling::Prioritized {
+                    alpha: params[0],
+                    beta: params[1],
+                }
+            }
+            _ => return Err(invalid_data("Unknown sampling strategy.")),
+        };
+        if capacity == 0 {
+            return Err(invalid_data("Replay buffer has no capacity."));
+        }
+
+        let mut buffer = Self::new(ReplayConfig { capacity, sampling });
+        let mut max_priority = [0.0];
+        read_floats(&mut reader, &mut max_priority)?;
+        buffer.max_priority = max_priority[0];
+        buffer.next = read_u64(&mut reader)? as usize;
+
+        let len = read_u64(&mut reader)? as usize;
+        if len > capacity || buffer.next >= capacity {
+            return Err(invalid_data("Replay buffer holds more than its capacity."));
+        }
+        if len < capacity && buffer.next != len {
+            return Err(invalid_data(
+                "Replay buffer isn't full, but doesn't append next.",
+            ));
+        }
+
+        for _ in 0..len {
+            let mut experience = Experience {
+                state: vec![0.0; observation_size].into(),
+                action: 0,
+                signal: 0,
+                reward: 0.0,
+                next_state: vec![0.0; observation_size].into(),
+                done: false,
+            };
+            let mut reward = [0.0];
+            let mut priority = [0.0];
+
+            read_floats(&mut reader, &mut experience.state[..])?;
+            experience.action = read_u8(&mut reader)?;
+            if experience.action as usize >= CreatureAction::NUM_ACTIONS {
+                return Err(invalid_data("Experience has an unknown action."));
+            }
+            experience.signal = read_u8(&mut reader)?;
+            read_floats(&mut reader, &mut reward)?;
+            read_floats(&mut reader, &mut experience.next_state[..])?;
+            experience.done = read_u8(&mut reader)? != 0;
+            read_floats(&mut reader, &mut priority)?;
+
+            experience.reward = reward[0];
+            buffer.experiences.push(experience);
+            buffer.priorities.push(0.0);
+            buffer.set_priority(buffer.len() - 1, priority[0]);
+        }
+
+        Ok(buffer)
+    }
+}
+
+/// Sums and minimums of the sampling weights of a buffer's experiences, kept in binary trees, so
+/// that sampling and weighting experiences doesn't have to go over the whole buffer.
+#[derive(Clone, PartialEq, Debug)]
+struct PriorityTree {
+    /// Number of leaves, a power of two. Leaf `i` is node `leaves + i`, and the children of node
+    /// `n` are `2 * n` and `2 * n + 1`. Node 0 is unused.
+    leaves: usize,
+    sums: Vec<f32>,
+    mins: Vec<f32>,
+}
+
+impl PriorityTree {
+    fn new(capacity: usize) -> Self {
+        let leaves = capacity.next_power_of_two();
+        Self {
+            leaves,
+            sums: vec![0.0; 2 * leaves],
+            mins: vec![f32::INFINITY; 2 * leaves],
+        }
+    }
+
+    fn set(&mut self, index: usize, value: f32) {
+        let mut node = self.leaves + index;
+        self.sums[node] = value;
+        self.mins[node] = value;
+
+        while node > 1 {
+            node /= 2;
+            self.sums[node] = self.sums[2 * node] + self.sums[2 * node + 1];
+            self.mins[node] = self.mins[2 * node].min(self.mins[2 * node + 1]);
+        }
+    }
+
+    fn get(&self, index: usize) -> f32 {
+        self.sums[self.leaves + index]
+    }
+
+    fn total(&self) -> f32 {
+        self.sums[1]
+    }
+
+    /// Smallest value that was set.
+    fn min(&self) -> f32 {
+        self.mins[1]
+    }
+
+    /// Index of the first leaf at which the running sum of values exceeds `target`.
+    fn find(&self, mut target: f32) -> usize {
+        let mut node = 1;
+        while node < self.leaves {
+            if target < self.sums[2 * node] {
+                node *= 2;
+            } else {
+                target -= self.sums[2 * node];
+                node = 2 * node + 1;
+            }
+        }
+
+        node - self.leaves
+    }
+}
+
+pub(super) fn write_floats(writer: &mut impl Write, floats: &[f32]) -> io::Result<()> {
+    for float in floats {
+        writer.write_all(&float.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub(super) fn read_floats(reader: &mut impl Read, floats: &mut [f32]) -> io::Result<()> {
+    let mut bytes = [0; 4];
+    for float in floats {
+        reader.read_exact(&mut bytes)?;
+        *float = f32::from_le_bytes(bytes);
+    }
+    Ok(())
+}
+
+pub(super) fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub(super) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(super) fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(super) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{Experience, ReplayBuffer, ReplayConfig, Sampling};
+
+    fn experience(reward: f32) -> Experience {
+        Experience {
+            state: vec![reward; 5].into(),
+            action: 2,
+            signal: 1,
+            reward,
+            next_state: vec![-reward; 5].into(),
+            done: reward < 0.0,
+        }
+    }
+
+    #[test]
+    fn full_buffer_replaces_the_oldest_experience() {
+        let mut buffer = ReplayBuffer::new(ReplayConfig {
+            capacity: 3,
+            sampling: Sampling::Uniform,
+        });
+        for reward in 0..5 {
+            buffer.push(experience(reward as f32));
+        }
+
+        let mut rewards: Vec<f32> = (0..buffer.len()).map(|i| buffer.get(i).reward).collect();
+        rewards.sort_by(f32::total_cmp);
+        assert_eq!(rewards, [2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn prioritized_sampling_prefers_high_priorities() {
+        let mut buffer = ReplayBuffer::new(ReplayConfig {
+            capacity: 2,
+            sampling: Sampling::Prioritized {
+                alpha: 1.0,
+                beta: 1.0,
+            },
+        });
+        buffer.push(experience(0.0));
+        buffer.push(experience(1.0));
+        buffer.update_priorities(&[0, 1], &[0.01, 10.0]);
+
+        let samples = buffer.sample(1000);
+        let high = samples.iter().filter(|i| **i == 1).count();
+        assert!(high > 950, "Sampled the high priority {high} times.");
+
+        let weights = buffer.weights(&[0, 1]);
+        assert_eq!(weights[0], 1.0);
+        assert!(weights[1] < weights[0]);
+    }
+
+    #[test]
+    fn saved_buffer_loads_unchanged() {
+        let mut buffer = ReplayBuffer::new(ReplayConfig::DEFAULT);
+        for reward in [-1.0, 0.5, 2.0] {
+            buffer.push(experience(reward));
+        }
+        buffer.update_priorities(&[1], &[3.0]);
+
+        let path = env::temp_dir().join(format!("replay-test-{}.bin", std::process::id()));
+        buffer.save(&path).expect("Failed to save replay buffer.");
+        let loaded = ReplayBuffer::load(&path).expect("Failed to load replay buffer.");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded, buffer);
+    }
+
+    #[test]
+    fn weights_stay_right_as_priorities_change() {
+        let (alpha, beta) = (0.6, 0.4);
+        let mut buffer = ReplayBuffer::new(ReplayConfig {
+            capacity: 5,
+            sampling: Sampling::Prioritized { alpha, beta },
+        });
+        for reward in 0..8 {
+            buffer.push(experience(reward as f32));
+            buffer.update_priorities(&[reward % 3], &[reward as f32 * 0.7]);
+        }
+
+        let powered: Vec<f32> = buffer.priorities.iter().map(|p| p.powf(alpha)).collect();
+        let total: f32 = powered.iter().sum();
+        let lowest = powered.iter().copied().fold(f32::MAX, f32::min);
+        let weight = |p: f32| (buffer.len() as f32 * p / total).powf(-beta);
+
+        let indices: Vec<usize> = (0..buffer.len()).collect();
+        for (i, cached) in buffer.weights(&indices).into_iter().enumerate() {
+            let scanned = weight(powered[i]) / weight(lowest);
+            assert!((cached - scanned).abs() < 1e-5, "{cached} != {scanned}");
+        }
+    }
+
+    #[test]
+    fn loading_rejects_a_buffer_that_would_overwrite_experiences() {
+        let mut buffer = ReplayBuffer::new(ReplayConfig::DEFAULT);
+        buffer.push(experience(1.0));
+        buffer.push(experience(2.0));
+        buffer.next = 0;
+
+        let path = env::temp_dir().join(format!("replay-next-test-{}.bin", std::process::id()));
+        buffer.save(&path).expect("Failed to save replay buffer.");
+        let loaded = ReplayBuffer::load(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Q-table backend. Much faster to train than the DQN and easy to inspect, but only sees the
+//! tiles right next to the creature.
+
+use std::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
+
+use rurel::{mdp::State, AgentTrainer};
+
+use crate::{
+    policy::Policy,
+    world::{
+        creature::{CreatureAction, CreatureState, StateKey},
+        grid::{Pos, Tile},
+        rules::{DefaultRules, Rules},
+    },
+};
+
+/// Learning rate of the Q-table.
+pub const ALPHA: f64 = 0.2;
+/// Discount factor of the Q-table.
+pub const GAMMA: f64 = 0.9;
+
+pub type TabularModel<R = DefaultRules> = AgentTrainer<CompactState<R>>;
+
+/// A [`CreatureState`] that is compared and hashed only by the [`Tile::code`]s of the 3x3 tiles
+/// around the creature and a bucket of how much food it has, so that similar situations share a
+/// row in the Q-table.
+///
+/// The full state is kept around for [`Rules::reward`] and [`Rules::actions`], so rules used with
+/// this backend should only decide actions based on the neighbouring tiles.
+#[derive(Clone)]
+pub struct CompactState<R: Rules = DefaultRules> {
+    key: StateKey,
+    state: CreatureState<R>,
+}
+
+impl<R: Rules> CompactState<R> {
+    /// Codes of the 3x3 tiles (row by row) followed by the food bucket.
+    pub fn key(&self) -> StateKey {
+        self.key
+    }
+
+    pub fn state(&self) -> &CreatureState<R> {
+        &self.state
+    }
+}
+
+impl<R: Rules> From<CreatureState<R>> for CompactState<R> {
+    fn from(state: CreatureState<R>) -> Self {
+        let own_species = match state.slice[Pos(3, 3)] {
+            Tile::Creature { species, .. } => Some(species),
+            _ => None,
+        };
+
+        let mut key = StateKey::default();
+        for y in 2..5 {
+            for x in 2..5 {
+                let pos = Pos(x, y);
+                key.push(
+                    state.slice[pos].code(own_species, pos == Pos(3, 3)) as u64,
+                    Tile::CODE_BITS,
+                );
+            }
+        }
+        key.push(food_bucket(state.food) as u64, 2);
+
+        Self { key, state }
+    }
+}
+
+/// Food of a creature, from -1 to 2, shifted to start at 0.
+fn food_bucket(food: isize) -> usize {
+    (food.clamp(-1, 2) + 1) as usize
+}
+
+impl<R: Rules> PartialEq for CompactState<R> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<R: Rules> Eq for CompactState<R> {}
+
+impl<R: Rules> Hash for CompactState<R> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+impl<R: Rules> Debug for CompactState<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for y in 0..3 {
+            for x in 0..3 {
+                write!(f, "{:?}", self.state.slice[Pos(x + 2, y + 2)])?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "food {}", self.state.food.clamp(-1, 2))
+    }
+}
+
+impl<R: Rules> State for CompactState<R> {
+    type A = CreatureAction;
+
+    fn reward(&self) -> f64 {
+        R::reward(&self.state)
+    }
+
+    fn actions(&self) -> Vec<Self::A> {
+        R::actions(&self.state)
+    }
+}
+
+impl<R: Rules> Policy<R> for TabularModel<R> {
+    /// Picks the legal action with the highest learned value, or a random one in situations that
+    /// were never seen during training. Never signals, since signals aren't part of the states it
+    /// learns over.
+    fn choose_action(&mut self, state: &CreatureState<R>) -> (CreatureAction, u8) {
+        let state = CompactState::from(state.clone());
+
+        let action = match self.expected_values(&state) {
+            Some(values) => state
+                .actions()
+                .into_iter()
+                .max_by(|a, b| {
+                    let value = |action| values.get(action).copied().unwrap_or(f64::MIN);
+                    value(a).total_cmp(&value(b))
+                })
+                .unwrap_or(CreatureAction::DoNothing),
+            None => state.random_action(),
+        };
+        (action, 0)
+    }
+}
@@ -1,10 +1,32 @@
-use raylib::color::Color;
+pub mod gif;
+pub mod png;
 
 pub const GRID_WIDTH: usize = 30;
 pub const GRID_HEIGHT: usize = 20;
 
 pub const TILE_SIZE: i32 = 16;
 
+/// RGBA color. Kept separate from raylib's color type so the simulation can be used without the
+/// `render` feature.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color::new(255, 255, 255, 255);
+    pub const BLACK: Color = Color::new(0, 0, 0, 255);
+    /// Background of the window and of exported frames. Same as raylib's `TAN`.
+    pub const TAN: Color = Color::new(210, 180, 140, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+}
+
 pub struct HEPT32;
 
 #[allow(unused)]
@@ -0,0 +1,347 @@
+//! Animated GIF encoder. Each frame gets its own color table, built from the colors that appear in
+//! it.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::util::Color;
+
+const MAX_COLORS: usize = 256;
+const MAX_CODE: u16 = 4096;
+
+pub struct GifEncoder<W: Write> {
+    writer: W,
+    width: u16,
+    height: u16,
+    /// Time each frame is shown for, in hundredths of a second.
+    delay: u16,
+}
+
+impl<W: Write> GifEncoder<W> {
+    pub fn new(mut writer: W, width: usize, height: usize, delay: u16) -> io::Result<Self> {
+        let (width, height) = (width as u16, height as u16);
+
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        // No global color table, background color 0, square pixels.
+        writer.write_all(&[0, 0, 0])?;
+
+        // Loop forever.
+        writer.write_all(&[0x21, 0xff, 0x0b])?;
+        writer.write_all(b"NETSCAPE2.0")?;
+        writer.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+
+        Ok(Self {
+            writer,
+            width,
+            height,
+            delay,
+        })
+    }
+
+    pub fn add_frame(&mut self, pixels: &[Color]) -> io::Result<()> {
+        let (palette, indices) = quantize(pixels);
+        let table_bits = (usize::BITS - (palette.len() - 1).max(1).leading_zeros()) as u8;
+
+        // Graphic control extension.
+        self.writer.write_all(&[0x21, 0xf9, 0x04, 0x00])?;
+        self.writer.write_all(&self.delay.to_le_bytes())?;
+        self.writer.write_all(&[0x00, 0x00])?;
+
+        // Image descriptor with a local color table.
+        self.writer.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.writer.write_all(&self.width.to_le_bytes())?;
+        self.writer.write_all(&self.height.to_le_bytes())?;
+        self.writer.write_all(&[0x80 | (table_bits - 1)])?;
+
+        for i in 0..1 << table_bits {
+            let color = palette.get(i).copied().unwrap_or(Color::BLACK);
+            self.writer.write_all(&[color.r, color.g, color.b])?;
+        }
+
+        let min_code_size = table_bits.max(2);
+        self.writer.write_all(&[min_code_size])?;
+        for block in lzw_encode(&indices, min_code_size).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0x3b])?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Builds a palette of at most 256 colors. Colors past that limit are mapped to the closest
+/// palette entry.
+fn quantize(pixels: &[Color]) -> (Vec<Color>, Vec<u8>) {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+
+    let indices = pixels
+        .iter()
+        .map(|color| {
+            let key = (color.r, color.g, color.b);
+            if let Some(index) = lookup.get(&key) {
+                return *index;
+            }
+
+            let index = if palette.len() < MAX_COLORS {
+                palette.push(*color);
+                (palette.len() - 1) as u8
+            } else {
+                closest(&palette, *color)
+            };
+            lookup.insert(key, index);
+
+            index
+        })
+        .collect();
+
+    (palette, indices)
+}
+
+fn closest(palette: &[Color], color: Color) -> u8 {
+    let distance = |other: &Color| {
+        let dr = other.r as i32 - color.r as i32;
+        let dg = other.g as i32 - color.g as i32;
+        let db = other.b as i32 - color.b as i32;
+        dr * dr + dg * dg + db * db
+    };
+
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, other)| distance(other))
+        .map(|(i, _)| i as u8)
+        .unwrap_or(0)
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    len: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.acc |= (code as u32) << self.len;
+        self.len += size;
+
+        while self.len >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+
+        self.bytes
+    }
+}
+
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    let mut out = BitWriter {
+        bytes: Vec::new(),
+        acc: 0,
+        len: 0,
+    };
+    let mut dict: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = clear + 2;
+    let mut code_size = min_code_size + 1;
+
+    out.write(clear, code_size);
+
+    let Some((first, rest)) = indices.split_first() else {
+        out.write(end, code_size);
+        return out.finish();
+    };
+
+    let mut prefix = *first as u16;
+    for index in rest {
+        if let Some(code) = dict.get(&(prefix, *index)) {
+            prefix = *code;
+            continue;
+        }
+
+        out.write(prefix, code_size);
+
+        if next < MAX_CODE {
+            dict.insert((prefix, *index), next);
+            if next == 1 << code_size {
+                code_size += 1;
+            }
+            next += 1;
+        } else {
+            out.write(clear, code_size);
+            dict.clear();
+            next = clear + 2;
+            code_size = min_code_size + 1;
+        }
+
+        prefix = *index as u16;
+    }
+
+    out.write(prefix, code_size);
+    out.write(end, code_size);
+
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lzw_encode, GifEncoder, MAX_CODE};
+    use crate::util::Color;
+
+    /// Reference GIF decoder for the LZW stream of one frame.
+    fn lzw_decode(bytes: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1u16 << min_code_size;
+        let end = clear + 1;
+        let initial: Vec<Vec<u8>> = (0..=end).map(|i| vec![i as u8]).collect();
+
+        let mut dict = initial.clone();
+        let mut code_size = min_code_size + 1;
+        let mut bit = 0;
+        let mut prev: Option<Vec<u8>> = None;
+        let mut out = Vec::new();
+
+        loop {
+            let mut code = 0u16;
+            for i in 0..code_size as usize {
+                if bytes[(bit + i) / 8] >> ((bit + i) % 8) & 1 == 1 {
+                    code |= 1 << i;
+                }
+            }
+            bit += code_size as usize;
+
+            if code == clear {
+                dict = initial.clone();
+                code_size = min_code_size + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                break;
+            }
+
+            let entry = match (dict.get(code as usize), &prev) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(prev)) => [&prev[..], &prev[..1]].concat(),
+                (None, None) => panic!("Unknown code {code} at the start of the stream."),
+            };
+            if let Some(prev) = prev {
+                if dict.len() < MAX_CODE as usize {
+                    dict.push([&prev[..], &entry[..1]].concat());
+                }
+            }
+            if dict.len() == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+
+            out.extend_from_slice(&entry);
+            prev = Some(entry);
+        }
+
+        out
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        // Enough pseudo-random data to fill the dictionary and clear it several times.
+        let mut seed = 1u32;
+        let noise: Vec<u8> = (0..50_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+
+        for min_code_size in [2, 4, 8] {
+            let max = (1u16 << min_code_size) - 1;
+            let repeating: Vec<u8> = (0..5000)
+                .map(|i| (i / 3 % (max as usize + 1)) as u8)
+                .collect();
+            let noise: Vec<u8> = noise
+                .iter()
+                .map(|i| (*i as u16 % (max + 1)) as u8)
+                .collect();
+
+            for indices in [vec![], vec![0], repeating, noise] {
+                assert_eq!(
+                    lzw_decode(&lzw_encode(&indices, min_code_size), min_code_size),
+                    indices
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_a_2x2_frame() {
+        let pixels = [
+            Color::new(255, 0, 0, 255),
+            Color::new(0, 255, 0, 255),
+            Color::new(0, 255, 0, 255),
+            Color::new(0, 0, 255, 255),
+        ];
+        let mut encoder =
+            GifEncoder::new(Vec::new(), 2, 2, 25).expect("Writing to a Vec can't fail.");
+        encoder
+            .add_frame(&pixels)
+            .expect("Writing to a Vec can't fail.");
+        let bytes = encoder.finish().expect("Writing to a Vec can't fail.");
+
+        assert_eq!(bytes[..6], *b"GIF89a");
+        assert_eq!(bytes[6..10], [2, 0, 2, 0]);
+        assert_eq!(bytes.last(), Some(&0x3b));
+
+        // Skip the logical screen descriptor and the looping extension.
+        let mut at = 13;
+        assert_eq!(bytes[at..at + 3], [0x21, 0xff, 0x0b]);
+        at += 3 + 11 + 5;
+        // Graphic control extension with the delay.
+        assert_eq!(bytes[at..at + 4], [0x21, 0xf9, 0x04, 0x00]);
+        assert_eq!(bytes[at + 4..at + 6], 25u16.to_le_bytes());
+        at += 8;
+
+        assert_eq!(bytes[at], 0x2c);
+        let flags = bytes[at + 9];
+        assert_eq!(flags & 0x80, 0x80);
+        at += 10;
+        let table_len = 1 << ((flags & 0x07) + 1);
+        let palette: Vec<Color> = bytes[at..at + table_len * 3]
+            .chunks(3)
+            .map(|rgb| Color::new(rgb[0], rgb[1], rgb[2], 255))
+            .collect();
+        at += table_len * 3;
+
+        let min_code_size = bytes[at];
+        at += 1;
+        let mut data = Vec::new();
+        while bytes[at] != 0 {
+            let len = bytes[at] as usize;
+            data.extend_from_slice(&bytes[at + 1..][..len]);
+            at += 1 + len;
+        }
+
+        let decoded: Vec<Color> = lzw_decode(&data, min_code_size)
+            .into_iter()
+            .map(|index| palette[index as usize])
+            .collect();
+        assert_eq!(decoded, pixels);
+        assert_eq!(bytes[at + 1..], [0x3b]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Minimal PNG encoder. Pixel data is stored uncompressed, which keeps the encoder tiny at the
+//! cost of larger files.
+
+use std::io::{self, Write};
+
+use crate::util::Color;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+/// Largest payload of a stored deflate block.
+const MAX_BLOCK_LEN: usize = 0xffff;
+
+pub fn encode(
+    writer: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    writer.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, default compression, filter and interlace methods.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &header)?;
+
+    let mut raw = Vec::with_capacity(height * (width * 4 + 1));
+    for row in pixels.chunks(width) {
+        // Filter type: none.
+        raw.push(0);
+        for color in row {
+            raw.extend_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+    }
+    write_chunk(writer, b"IDAT", &zlib_stored(&raw))?;
+
+    write_chunk(writer, b"IEND", &[])
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+
+    let crc = crc32(kind.iter().chain(data));
+    writer.write_all(&crc.to_be_bytes())
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK_LEN * 5 + 11);
+    // Deflate with a 32K window, no preset dictionary.
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_BLOCK_LEN).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, crc32, encode, zlib_stored, MAX_BLOCK_LEN, SIGNATURE};
+    use crate::util::Color;
+
+    /// Reads back the data of a zlib stream made of stored blocks, checking its checksum.
+    fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(stream[..2], [0x78, 0x01]);
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+
+        let mut data = Vec::new();
+        let mut at = 2;
+        loop {
+            let last = stream[at] == 1;
+            let len = u16::from_le_bytes([stream[at + 1], stream[at + 2]]);
+            let nlen = u16::from_le_bytes([stream[at + 3], stream[at + 4]]);
+            assert_eq!(len, !nlen);
+
+            data.extend_from_slice(&stream[at + 5..][..len as usize]);
+            at += 5 + len as usize;
+            if last {
+                break;
+            }
+        }
+
+        assert_eq!(stream[at..], adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn stored_zlib_round_trips() {
+        let long: Vec<u8> = (0..MAX_BLOCK_LEN * 2 + 10)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        for data in [&[][..], b"survival", &long] {
+            assert_eq!(inflate_stored(&zlib_stored(data)), data);
+        }
+    }
+
+    #[test]
+    fn encodes_a_2x2_image() {
+        let pixels = [
+            Color::new(255, 0, 0, 255),
+            Color::new(0, 255, 0, 255),
+            Color::new(0, 0, 255, 128),
+            Color::new(1, 2, 3, 0),
+        ];
+        let mut bytes = Vec::new();
+        encode(&mut bytes, 2, 2, &pixels).expect("Writing to a Vec can't fail.");
+
+        assert_eq!(bytes[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < bytes.len() {
+            let len = u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+            let kind = &bytes[at + 4..at + 8];
+            let data = &bytes[at + 8..][..len];
+            let crc = u32::from_be_bytes(bytes[at + 8 + len..][..4].try_into().unwrap());
+
+            assert_eq!(crc, crc32(kind.iter().chain(data)));
+            chunks.push((kind, data));
+            at += 12 + len;
+        }
+
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [&b"IHDR"[..], b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+        assert_eq!(
+            inflate_stored(chunks[1].1),
+            [
+                0, 255, 0, 0, 255, 0, 255, 0, 255, //
+                0, 0, 0, 255, 128, 1, 2, 3, 0,
+            ]
+        );
+    }
+}
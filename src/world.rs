@@ -1,12 +1,28 @@
-use std::{cell::RefCell, rc::Rc};
+use std::marker::PhantomData;
 
+use creature::{CreatureAction, CreatureState};
+use day::{DayCycle, Phase};
+use food::{FoodConfig, MeatConfig, ResourceConfig};
 use grid::{Grid, Pos, Tile};
+use rules::{DefaultRules, Rules};
+use scent::ScentMap;
 use species::{Species, SpeciesConfig};
+use structure::{BuildConfig, StructureConfig};
 
-use crate::util::{GRID_HEIGHT, GRID_WIDTH};
+use crate::{
+    policy::{DqnConfig, PolicyKind},
+    util::{GRID_HEIGHT, GRID_WIDTH, HEPT32},
+};
 
+pub mod creature;
+pub mod day;
+pub mod food;
 pub mod grid;
+pub mod observation;
+pub mod rules;
+pub mod scent;
 pub mod species;
+pub mod structure;
 
 #[derive(Clone, Copy)]
 pub struct SimConfig {
@@ -14,45 +30,163 @@ pub struct SimConfig {
     pub moon_len: usize,
     /// Number of bushes to spawn.
     pub num_food: usize,
-    /// Chance that a given bush regrows in between moons.
-    pub chance_regrow: f64,
+    /// How bushes change in between moons.
+    pub food: FoodConfig,
+    /// Fraction of scent that is left after each step.
+    pub scent_decay: f32,
+    /// Fraction of the scent on a tile that spreads to its neighbours each step.
+    pub scent_diffusion: f32,
+    /// Days and nights within each moon. Use [`DayCycle::ALWAYS_DAY`] to turn off nights.
+    pub day_cycle: DayCycle,
+    /// Costs of walls, nests and traps. Use [`BuildConfig::DISABLED`] to turn off building.
+    pub build: BuildConfig,
     /// List of species to include in simulation
     pub species: &'static [SpeciesConfig],
 }
 
-pub struct World {
-    pub grid: Rc<RefCell<Grid<GRID_WIDTH, GRID_HEIGHT>>>,
+impl SimConfig {
+    /// The game played by the binary: packs of DQN creatures against nocturnal loners that learn
+    /// a Q-table, with every kind of food, nights and building. Benchmarks and tests start from
+    /// this and change what they need.
+    pub const DEFAULT: Self = Self {
+        moon_len: 20,
+        num_food: 20,
+        food: FoodConfig {
+            berry_nutrition: 1,
+            regrow: &[0.7, 0.5, 0.2, 0.4],
+            chance_spread: 0.1,
+            overgrazing_moons: 3,
+            carrying_capacity: 40,
+            roots: ResourceConfig {
+                nutrition: 2,
+                hits: 3,
+                regrow: 0.3,
+                initial: 6,
+            },
+            fruit: ResourceConfig {
+                nutrition: 4,
+                hits: 1,
+                regrow: 0.2,
+                initial: 2,
+            },
+            meat: MeatConfig {
+                nutrition: 1,
+                bites: 3,
+                rot: 0.5,
+            },
+        },
+        scent_decay: 0.9,
+        scent_diffusion: 0.1,
+        day_cycle: DayCycle {
+            day_len: 12,
+            night_len: 8,
+            night_view_radius: 1,
+        },
+        build: BuildConfig {
+            wall: StructureConfig { cost: 1, hp: 4 },
+            wall_decay: 1,
+            repair_cost: 1,
+            nest: StructureConfig { cost: 3, hp: 3 },
+            trap: StructureConfig { cost: 2, hp: 2 },
+            hatch_cost: 4,
+            trap_damage: 1,
+            trap_steps: 3,
+        },
+        species: &[
+            SpeciesConfig {
+                color: HEPT32::RED,
+                num_creatures: 4,
+                num_packs: 2,
+                policy: PolicyKind::Dqn(DqnConfig::DEFAULT),
+                signals: 0,
+                nocturnal: false,
+            },
+            SpeciesConfig {
+                color: HEPT32::BLUE,
+                num_creatures: 1,
+                num_packs: 8,
+                policy: PolicyKind::Tabular,
+                signals: 2,
+                nocturnal: true,
+            },
+        ],
+    };
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone)]
+pub struct World<R: Rules = DefaultRules> {
+    pub grid: Grid<GRID_WIDTH, GRID_HEIGHT>,
+    pub scent: ScentMap,
+    /// Number of moons in a row that the bush on each tile has ended without berries.
+    pub bare_moons: [[usize; GRID_WIDTH]; GRID_HEIGHT],
     pub species: Vec<Species>,
+    /// Steps left until the current moon ends.
     pub time_left: usize,
+    /// Number of moons that have ended so far.
+    pub moon: usize,
     pub config: SimConfig,
+    rules: PhantomData<R>,
 }
 
 impl World {
+    /// Creates a world that plays by the [`DefaultRules`].
     pub fn new(config: SimConfig) -> Self {
+        Self::with_rules(config)
+    }
+}
+
+impl<R: Rules> World<R> {
+    /// Creates a world that plays by a custom set of rules, eg.
+    /// `World::<MyRules>::with_rules(config)`.
+    pub fn with_rules(config: SimConfig) -> Self {
+        assert!(
+            config.food.roots.hits > 0,
+            "Roots have to take at least one hit to dig up."
+        );
         let mut grid = Grid::empty();
 
-        for _i in 0..config.num_food {
-            let mut pos;
+        let food = [
+            (config.num_food, Tile::Bush(true)),
+            (
+                config.food.roots.initial,
+                Tile::Roots(config.food.roots.hits),
+            ),
+            (config.food.fruit.initial, Tile::Fruit(true)),
+        ];
+        for (count, tile) in food {
+            for _i in 0..count {
+                let mut pos;
 
-            loop {
-                pos = Pos(
-                    (rand::random::<f64>() * GRID_WIDTH as f64).floor() as usize,
-                    (rand::random::<f64>() * GRID_HEIGHT as f64).floor() as usize,
-                );
+                loop {
+                    pos = Pos(
+                        (rand::random::<f64>() * GRID_WIDTH as f64).floor() as usize,
+                        (rand::random::<f64>() * GRID_HEIGHT as f64).floor() as usize,
+                    );
 
-                if let Tile::Empty = grid[pos] {
-                    break;
+                    if let Tile::Empty = grid[pos] {
+                        break;
+                    }
                 }
-            }
 
-            grid[pos] = Tile::Bush(true);
+                grid[pos] = tile;
+            }
         }
 
         let mut s = Self {
-            grid: Rc::new(RefCell::new(grid)),
+            grid,
+            scent: ScentMap::new(config.species.len()),
+            bare_moons: [[0; GRID_WIDTH]; GRID_HEIGHT],
             species: Vec::new(),
             time_left: config.moon_len,
+            moon: 0,
             config,
+            rules: PhantomData,
         };
 
         for species in config.species {
@@ -63,7 +197,7 @@ impl World {
     }
 
     fn add_species(&mut self, config: SpeciesConfig) {
-        let species = Species::new(self.species.len(), &self, config.color);
+        let mut species = Species::new(self.species.len(), self, config);
 
         for _ in 0..config.num_packs {
             let mut pos;
@@ -75,7 +209,7 @@ impl World {
                 );
 
                 if pos.0 <= GRID_WIDTH - 4 && pos.1 <= GRID_HEIGHT - 4 {
-                    if let Tile::Empty = self.grid.borrow()[pos] {
+                    if let Tile::Empty = self.grid[pos] {
                         break;
                     }
                 }
@@ -90,79 +224,202 @@ impl World {
                         (rand::random::<f64>() * 5.0).floor() as usize,
                     );
 
-                    if let Tile::Empty = self.grid.borrow()[pos + offset] {
+                    if let Tile::Empty = self.grid[pos + offset] {
                         break;
                     }
                 }
 
-                self.grid.borrow_mut()[pos + offset] = Tile::Creature {
+                self.grid[pos + offset] = Tile::Creature {
                     species: species.id,
                     color: species.color,
                     food: 0,
                 };
-                species.members.borrow_mut().push(pos + offset);
+                species.members.push(pos + offset);
             }
         }
 
         self.species.push(species);
     }
 
-    pub fn finish_step(&self) {
-        for species in &self.species {
-            let mut creatures = species.members.borrow_mut();
+    pub fn finish_step(&mut self) {
+        R::finish_step(self);
 
-            let mut indices = Vec::new();
-            let mut clear_pos = Vec::new();
-            for (i, creature) in creatures.iter().enumerate() {
-                if let Tile::Creature { food, .. } = self.grid.borrow()[*creature] {
-                    if food < 0 {
-                        clear_pos.push(*creature);
-                        indices.push(i);
-                    }
-                } else {
-                    clear_pos.push(*creature);
-                    indices.push(i);
-                }
-            }
+        let phase = self.phase();
+        for species in &mut self.species {
+            species.update_memories(&self.grid, phase);
+            species.update_signals();
 
-            indices.reverse();
-            for i in indices {
-                creatures.remove(i);
+            for pos in &species.members {
+                self.scent.deposit(species.id, *pos, scent::DEPOSIT);
             }
+        }
+        self.scent
+            .update(self.config.scent_decay, self.config.scent_diffusion);
+    }
+
+    pub fn finish_moon(&mut self) {
+        R::finish_moon(self)
+    }
+
+    /// Lets member `index` of species `id` take `action`, and send `signal` to its allies alongside
+    /// it. See [`Species::emit`].
+    pub fn act(&mut self, id: usize, index: usize, action: CreatureAction, signal: u8) {
+        R::handle_action(self, id, action, index);
+        self.species[id].emit(index, signal);
+    }
 
-            for pos in clear_pos {
-                self.grid.borrow_mut()[pos] = Tile::Empty;
+    /// Lets every living creature act once, then ends the step (and the moon, if this was its
+    /// last step). `policy` is given the id of the creature's species and what the creature can
+    /// see, and picks its action, which should be one of [`Rules::actions`], and the signal it
+    /// sends alongside it.
+    pub fn step(
+        &mut self,
+        mut policy: impl FnMut(usize, &CreatureState<R>) -> (CreatureAction, u8),
+    ) {
+        let time = self.moon_step();
+        for id in 0..self.species.len() {
+            for index in 0..self.species[id].members.len() {
+                let state = CreatureState::new(self, &self.species[id], time, index);
+                let (action, signal) = policy(id, &state);
+                self.act(id, index, action, signal);
             }
         }
+
+        self.end_step();
     }
 
-    pub fn finish_moon(&self) {
-        let mut grid = self.grid.borrow_mut();
-        for (y, row) in grid.arr().iter().enumerate() {
-            for (x, tile) in row.iter().enumerate() {
-                match *tile {
-                    Tile::Bush(false) => {
-                        if rand::random::<f64>() > self.config.chance_regrow {
-                            grid[Pos(x, y)] = Tile::Bush(true);
-                        }
-                    }
-                    Tile::Creature {
-                        food,
-                        species,
-                        color,
-                    } => {
-                        grid[Pos(x, y)] = Tile::Creature {
-                            species,
-                            color,
-                            food: food - 2,
-                        };
+    /// Like [`Self::step`], but each species picks the actions of all its members at once, so
+    /// `policy` can evaluate them as a batch. It is given the world at the start of the species'
+    /// turn and the id of the species, and returns an action and signal per member in order.
+    /// Members still act one after the other, and an action that an ally acting first made
+    /// illegal is replaced with [`CreatureAction::DoNothing`].
+    pub fn step_batched(
+        &mut self,
+        mut policy: impl FnMut(&Self, usize) -> Vec<(CreatureAction, u8)>,
+    ) {
+        let time = self.moon_step();
+        for id in 0..self.species.len() {
+            let actions = policy(self, id);
+            assert_eq!(
+                actions.len(),
+                self.species[id].members.len(),
+                "Policy returned the wrong number of actions."
+            );
+
+            for (index, (mut action, signal)) in actions.into_iter().enumerate() {
+                // The first member sees the world its action was picked for.
+                if index > 0 && action != CreatureAction::DoNothing {
+                    let current = CreatureState::new(self, &self.species[id], time, index);
+                    if !R::actions(&current).contains(&action) {
+                        action = CreatureAction::DoNothing;
                     }
-                    _ => (),
                 }
+                self.act(id, index, action, signal);
             }
         }
 
-        drop(grid);
-        self.finish_step()
+        self.end_step();
+    }
+
+    /// Ends a step once every creature has acted, and the moon if this was its last step.
+    pub fn end_step(&mut self) {
+        self.time_left -= 1;
+        self.finish_step();
+
+        if self.time_left == 0 {
+            self.time_left = self.config.moon_len;
+            self.moon += 1;
+            self.finish_moon();
+        }
+    }
+
+    /// Number of steps taken since the start of the current moon.
+    pub fn moon_step(&self) -> usize {
+        self.config.moon_len - self.time_left
+    }
+
+    /// Whether the current step is during the day or at night.
+    pub fn phase(&self) -> Phase {
+        self.config.day_cycle.phase(self.moon_step())
+    }
+
+    pub fn tile(&self, pos: Pos) -> Tile {
+        self.grid[pos]
+    }
+
+    /// Total number of living creatures, across all species.
+    pub fn population(&self) -> usize {
+        self.species.iter().map(|s| s.members.len()).sum()
+    }
+
+    pub fn is_extinct(&self) -> bool {
+        self.population() == 0
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{
+        grid::{Grid, Pos, Tile},
+        species::{Species, SpeciesConfig},
+        SimConfig, World,
+    };
+    use crate::{policy::PolicyKind, util::HEPT32};
+
+    /// The default game with species that don't learn: packs that can signal, and nocturnal
+    /// loners.
+    pub(crate) const CONFIG: SimConfig = SimConfig {
+        species: &[
+            SpeciesConfig {
+                color: HEPT32::RED,
+                num_creatures: 4,
+                num_packs: 3,
+                policy: PolicyKind::Random,
+                signals: 2,
+                nocturnal: false,
+            },
+            SpeciesConfig {
+                color: HEPT32::BLUE,
+                num_creatures: 1,
+                num_packs: 8,
+                policy: PolicyKind::Random,
+                signals: 0,
+                nocturnal: true,
+            },
+        ],
+        ..SimConfig::DEFAULT
+    };
+
+    /// World of `config` with nothing on the grid but `creatures`, given as the species, position
+    /// and food of each.
+    pub(crate) fn world_with(config: SimConfig, creatures: &[(usize, Pos, isize)]) -> World {
+        let mut world = World::new(config);
+        world.grid = Grid::empty();
+        world.species = config
+            .species
+            .iter()
+            .enumerate()
+            .map(|(id, species)| Species::new(id, &world, *species))
+            .collect();
+
+        for (id, pos, food) in creatures {
+            let species = &mut world.species[*id];
+            world.grid[*pos] = Tile::Creature {
+                species: *id,
+                color: species.color,
+                food: *food,
+            };
+            species.members.push(*pos);
+        }
+
+        world
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one hit")]
+    fn roots_that_take_no_hits_are_rejected() {
+        let mut config = CONFIG;
+        config.food.roots.hits = 0;
+        World::new(config);
     }
 }
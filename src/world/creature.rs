@@ -0,0 +1,402 @@
+//! What a creature can see and do.
+
+use std::marker::PhantomData;
+
+use super::{
+    day::Phase,
+    grid::{Grid, Pos, Tile},
+    rules::{DefaultRules, Rules},
+    species::Species,
+    structure::BuildConfig,
+    World,
+};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum CreatureAction {
+    Move(i8, i8),
+    Attack(i8, i8),
+    BuildWall(i8, i8),
+    /// Gives [`BuildConfig::repair_cost`] food to restore a hit point of its species' wall.
+    RepairWall(i8, i8),
+    /// Leaves one food on an empty tile or cache, for later.
+    DropFood(i8, i8),
+    /// Takes one food from a cache.
+    PickUp(i8, i8),
+    /// Gives one food to another creature.
+    GiveFood(i8, i8),
+    /// Builds a nest of the creature's species. See [`BuildConfig`].
+    BuildNest(i8, i8),
+    /// Builds a trap of the creature's species.
+    BuildTrap(i8, i8),
+    DoNothing,
+}
+
+impl CreatureAction {
+    pub const DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+    /// Number of distinct actions: each kind aimed at each of [`Self::DIRECTIONS`], plus doing
+    /// nothing.
+    pub const NUM_ACTIONS: usize = 9 * Self::DIRECTIONS.len() + 1;
+
+    /// Index into [`Self::DIRECTIONS`] of the direction the action is aimed at, if any.
+    pub fn direction_index(&self) -> Option<usize> {
+        match *self {
+            CreatureAction::Move(x, y)
+            | CreatureAction::Attack(x, y)
+            | CreatureAction::BuildWall(x, y)
+            | CreatureAction::RepairWall(x, y)
+            | CreatureAction::DropFood(x, y)
+            | CreatureAction::PickUp(x, y)
+            | CreatureAction::GiveFood(x, y)
+            | CreatureAction::BuildNest(x, y)
+            | CreatureAction::BuildTrap(x, y) => Self::DIRECTIONS
+                .iter()
+                .position(|direction| *direction == (x, y)),
+            CreatureAction::DoNothing => None,
+        }
+    }
+
+    /// Index of the action below [`Self::NUM_ACTIONS`]: actions of the same kind are next to each
+    /// other, ordered like [`Self::DIRECTIONS`], and doing nothing comes last. `None` for actions
+    /// aimed anywhere else.
+    pub fn index(&self) -> Option<usize> {
+        let kind = match self {
+            CreatureAction::Move(..) => 0,
+            CreatureAction::Attack(..) => 1,
+            CreatureAction::BuildWall(..) => 2,
+            CreatureAction::RepairWall(..) => 3,
+            CreatureAction::DropFood(..) => 4,
+            CreatureAction::PickUp(..) => 5,
+            CreatureAction::GiveFood(..) => 6,
+            CreatureAction::BuildNest(..) => 7,
+            CreatureAction::BuildTrap(..) => 8,
+            CreatureAction::DoNothing => return Some(Self::NUM_ACTIONS - 1),
+        };
+
+        self.direction_index()
+            .map(|direction| kind * Self::DIRECTIONS.len() + direction)
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct CreatureState<R: Rules = DefaultRules> {
+    /// 7x7 grid centered on creature. Tiles it can't see (because they are outside of the world
+    /// or too dark) are [`Tile::OutOfBounds`].
+    pub slice: Grid<7, 7>,
+    /// Amount of food that the creature has.
+    pub food: isize,
+    /// How much time is left until the moon ends. (and it has to eat)
+    pub time: usize,
+    /// Number of steps in a moon.
+    pub moon_len: usize,
+    /// Offsets to tiles the creature remembers having food, nearest first. See
+    /// [`Memory`](super::species::Memory).
+    pub remembered_food: Vec<(isize, isize)>,
+    /// Signals sent last step by allies in view, laid out like `slice`. Zero means no signal.
+    pub signals: [[u8; 7]; 7],
+    /// Number of distinct signals the creature can send alongside its action, besides zero for
+    /// none.
+    pub num_signals: u8,
+    /// Scent of the creature's species in view, laid out like `slice`. See
+    /// [`ScentMap::view`](super::scent::ScentMap::view).
+    pub own_scent: [[u8; 7]; 7],
+    /// Scent of all other species in view.
+    pub other_scent: [[u8; 7]; 7],
+    /// Whether it is day or night.
+    pub phase: Phase,
+    /// Steps the creature still can't move for, after being caught in a trap.
+    pub stuck: usize,
+    /// Costs of what the creature can build.
+    pub build: BuildConfig,
+    rules: PhantomData<R>,
+}
+
+impl<R: Rules> CreatureState<R> {
+    pub fn new(world: &World<R>, species: &Species, time: usize, index: usize) -> Self {
+        let phase = species.day_cycle.phase(time);
+        let (own_scent, other_scent) = world.scent.view(species.id, species.members[index]);
+
+        Self {
+            slice: species.get_visible_slice(&world.grid, index, phase),
+            food: species.get_food(&world.grid, index),
+            time,
+            moon_len: world.config.moon_len,
+            remembered_food: species.recall_food(index),
+            signals: species.get_visible_signals(index, phase),
+            num_signals: species.num_signals,
+            own_scent,
+            other_scent,
+            phase,
+            stuck: species.get_stuck(index),
+            build: species.build,
+            rules: PhantomData,
+        }
+    }
+
+    /// Packs the state into a [`StateKey`].
+    pub fn key(&self) -> StateKey {
+        let own_species = match self.slice[Pos(3, 3)] {
+            Tile::Creature { species, .. } => Some(species),
+            _ => None,
+        };
+        let mut key = StateKey::default();
+
+        for pos in Grid::<7, 7>::positions() {
+            let code = self.slice[pos].code(own_species, pos == Pos(3, 3));
+            key.push(code as u64, Tile::CODE_BITS);
+        }
+        key.push(
+            self.food.clamp(i16::MIN as isize, i16::MAX as isize) as i16 as u16 as u64,
+            16,
+        );
+        key.push(self.time.min(u16::MAX as usize) as u64, 16);
+        key.push((self.phase == Phase::Night) as u64, 1);
+        key.push(self.stuck.min(u8::MAX as usize) as u64, 8);
+
+        key
+    }
+}
+
+/// Bit-packed copy of a [`CreatureState`], for keying tables by what a creature sees. Holds the
+/// [`Tile::code`] of every tile in view, followed by the creature's food and the time left (both
+/// saturated to 16 bits), whether it is night and how long the creature is stuck (saturated to 8
+/// bits). Signals, scent and memory are left out. Coarser keys can be built with
+/// [`StateKey::push`].
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Default, Debug)]
+pub struct StateKey {
+    words: [u64; 4],
+    /// Number of bits pushed so far.
+    len: u32,
+}
+
+impl StateKey {
+    /// Appends the lowest `bits` bits of `value`.
+    pub fn push(&mut self, value: u64, bits: u32) {
+        assert!(
+            self.len + bits <= u64::BITS * self.words.len() as u32,
+            "State key is full."
+        );
+
+        for i in 0..bits {
+            if value >> i & 1 == 1 {
+                let at = self.len + i;
+                self.words[at as usize / 64] |= 1 << (at % 64);
+            }
+        }
+        self.len += bits;
+    }
+
+    pub fn words(&self) -> [u64; 4] {
+        self.words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, marker::PhantomData};
+
+    use super::{CreatureAction, CreatureState};
+    use crate::{
+        util::Color,
+        world::{
+            day::Phase,
+            grid::{Grid, Pos, Tile},
+            structure::BuildConfig,
+            tests::{world_with, CONFIG},
+        },
+    };
+
+    fn state(slice: Grid<7, 7>, food: isize, time: usize) -> CreatureState {
+        CreatureState {
+            slice,
+            food,
+            time,
+            moon_len: 20,
+            remembered_food: Vec::new(),
+            signals: [[0; 7]; 7],
+            num_signals: 0,
+            own_scent: [[0; 7]; 7],
+            other_scent: [[0; 7]; 7],
+            phase: Phase::Day,
+            stuck: 0,
+            build: BuildConfig::DISABLED,
+            rules: PhantomData,
+        }
+    }
+
+    /// Tiles that a creature of species 0 tells apart, other than the observer itself.
+    fn tiles() -> [Tile; 9] {
+        [
+            Tile::Empty,
+            Tile::OutOfBounds,
+            Tile::Bush(true),
+            Tile::Bush(false),
+            Tile::Wall {
+                species: 0,
+                color: Color::BLACK,
+                hp: 1,
+            },
+            Tile::Wall {
+                species: 1,
+                color: Color::BLACK,
+                hp: 1,
+            },
+            Tile::Creature {
+                species: 0,
+                color: Color::BLACK,
+                food: 0,
+            },
+            Tile::Creature {
+                species: 1,
+                color: Color::BLACK,
+                food: 0,
+            },
+            Tile::Creature {
+                species: 100,
+                color: Color::BLACK,
+                food: 0,
+            },
+        ]
+    }
+
+    /// View of a creature of species 0 with nothing around it.
+    fn observer() -> Grid<7, 7> {
+        let mut slice = Grid::empty();
+        slice[Pos(3, 3)] = tiles()[6];
+        slice
+    }
+
+    #[test]
+    fn every_tile_in_view_changes_the_key() {
+        let mut keys = HashSet::new();
+        let mut count = 0;
+
+        for pos in Grid::<7, 7>::positions().filter(|pos| *pos != Pos(3, 3)) {
+            // Enemies of different species look the same.
+            for tile in tiles().into_iter().skip(1).take(7) {
+                let mut slice = observer();
+                slice[pos] = tile;
+                keys.insert(state(slice, 0, 0).key());
+                count += 1;
+            }
+        }
+        keys.insert(state(observer(), 0, 0).key());
+        count += 1;
+
+        assert_eq!(keys.len(), count);
+    }
+
+    #[test]
+    fn enemy_species_ids_do_not_change_the_key() {
+        let mut slice = observer();
+        slice[Pos(1, 2)] = tiles()[7];
+        let mut other = observer();
+        other[Pos(1, 2)] = tiles()[8];
+
+        assert_eq!(state(slice, 0, 0).key(), state(other, 0, 0).key());
+    }
+
+    #[test]
+    fn food_time_phase_and_stuck_change_the_key() {
+        let mut keys = HashSet::new();
+
+        for food in -3..=3 {
+            for time in 0..20 {
+                keys.insert(state(observer(), food, time).key());
+            }
+        }
+        let mut night = state(observer(), 0, 0);
+        night.phase = Phase::Night;
+        keys.insert(night.key());
+        let mut stuck = state(observer(), 0, 0);
+        stuck.stuck = 2;
+        keys.insert(stuck.key());
+
+        assert_eq!(keys.len(), 7 * 20 + 2);
+    }
+
+    #[test]
+    fn equal_states_have_equal_keys() {
+        let mut slice = observer();
+        slice[Pos(1, 5)] = Tile::Bush(true);
+
+        assert_eq!(state(slice, 2, 5).key(), state(slice, 2, 5).key());
+        assert!(state(slice, 2, 5) == state(slice, 2, 5));
+    }
+
+    #[test]
+    fn keys_fit_in_their_words() {
+        let mut slice = Grid::empty();
+        for pos in Grid::<7, 7>::positions() {
+            slice[pos] = tiles()[4];
+        }
+
+        let mut full = state(slice, -1, usize::MAX);
+        full.phase = Phase::Night;
+        full.stuck = usize::MAX;
+        let key = full.key();
+
+        let bits = 49 * Tile::CODE_BITS + 41;
+        assert_eq!(key.words()[3] >> (bits - 3 * 64), 0);
+        assert_ne!(key.words()[3], 0);
+    }
+
+    #[test]
+    fn only_nocturnal_creatures_see_as_far_at_night() {
+        // Two pack mates two tiles apart, one signalling, and a nocturnal loner below them.
+        let mut world = world_with(
+            CONFIG,
+            &[(0, Pos(5, 5), 2), (0, Pos(7, 5), 2), (1, Pos(5, 7), 2)],
+        );
+        world.species[0].emit(1, 2);
+        world.species[0].update_signals();
+        let night = CONFIG.day_cycle.day_len;
+
+        let day = CreatureState::new(&world, &world.species[0], 0, 0);
+        assert!(matches!(
+            day.slice[Pos(5, 3)],
+            Tile::Creature { species: 0, .. }
+        ));
+        assert_eq!(day.signals[3][5], 2);
+
+        let dark = CreatureState::new(&world, &world.species[0], night, 0);
+        assert_eq!(dark.phase, Phase::Night);
+        assert_eq!(dark.slice[Pos(5, 3)], Tile::OutOfBounds);
+        assert_eq!(dark.signals[3][5], 0);
+
+        let nocturnal = CreatureState::new(&world, &world.species[1], night, 0);
+        assert!(matches!(
+            nocturnal.slice[Pos(3, 1)],
+            Tile::Creature { species: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn every_action_has_its_own_index() {
+        let mut indices = HashSet::new();
+        for (x, y) in CreatureAction::DIRECTIONS {
+            for action in [
+                CreatureAction::Move(x, y),
+                CreatureAction::Attack(x, y),
+                CreatureAction::BuildWall(x, y),
+                CreatureAction::RepairWall(x, y),
+                CreatureAction::DropFood(x, y),
+                CreatureAction::PickUp(x, y),
+                CreatureAction::GiveFood(x, y),
+                CreatureAction::BuildNest(x, y),
+                CreatureAction::BuildTrap(x, y),
+            ] {
+                indices.insert(action.index().expect("Action has no index."));
+            }
+        }
+        indices.insert(
+            CreatureAction::DoNothing
+                .index()
+                .expect("Action has no index."),
+        );
+
+        assert_eq!(indices.len(), CreatureAction::NUM_ACTIONS);
+        assert!(indices.iter().all(|i| *i < CreatureAction::NUM_ACTIONS));
+        assert_eq!(CreatureAction::Move(1, 1).index(), None);
+    }
+}
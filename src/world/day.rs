@@ -0,0 +1,47 @@
+//! Days and nights within a moon.
+
+use super::observation::VIEW_RADIUS;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Phase {
+    Day,
+    Night,
+}
+
+/// Every moon starts with `day_len` steps of day, followed by `night_len` steps of night, and
+/// repeats that until it ends.
+#[derive(Clone, Copy, Debug)]
+pub struct DayCycle {
+    pub day_len: usize,
+    pub night_len: usize,
+    /// How far creatures that aren't nocturnal can see at night. At least 1, so they can always
+    /// see what they could act on.
+    pub night_view_radius: usize,
+}
+
+impl DayCycle {
+    /// A cycle without nights.
+    pub const ALWAYS_DAY: Self = Self {
+        day_len: 1,
+        night_len: 0,
+        night_view_radius: VIEW_RADIUS,
+    };
+
+    /// Phase of the `step`th step of a moon.
+    pub fn phase(&self, step: usize) -> Phase {
+        if self.night_len == 0 || step % (self.day_len + self.night_len) < self.day_len {
+            Phase::Day
+        } else {
+            Phase::Night
+        }
+    }
+
+    /// How far a creature can see during `phase`. Nocturnal creatures see just as far at night as
+    /// during the day.
+    pub fn view_radius(&self, phase: Phase, nocturnal: bool) -> usize {
+        match phase {
+            Phase::Night if !nocturnal => self.night_view_radius.clamp(1, VIEW_RADIUS),
+            _ => VIEW_RADIUS,
+        }
+    }
+}
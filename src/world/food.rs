@@ -0,0 +1,69 @@
+//! Kinds of food and how the food supply changes over time.
+
+/// Settings for roots and fruit trees, the kinds of food other than berries that grow.
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceConfig {
+    /// Food gained from eating it.
+    pub nutrition: isize,
+    /// For roots, how many times they have to be hit to be dug up, at least 1. Ignored for fruit.
+    pub hits: u8,
+    /// Chance that it regrows in between moons.
+    pub regrow: f64,
+    /// How many are spawned at the start.
+    pub initial: usize,
+}
+
+/// Settings for meat, which is left behind in a carcass when a creature starves.
+#[derive(Clone, Copy, Debug)]
+pub struct MeatConfig {
+    /// Food gained from each bite.
+    pub nutrition: isize,
+    /// How many times a carcass can be eaten from. Zero means starved creatures leave nothing.
+    pub bites: u8,
+    /// Chance that a carcass rots away in between moons.
+    pub rot: f64,
+}
+
+/// Controls the kinds of food and how they regrow, spread and die in between moons.
+#[derive(Clone, Copy, Debug)]
+pub struct FoodConfig {
+    /// Food gained from eating berries off a bush.
+    pub berry_nutrition: isize,
+    /// Chance that a bush without berries regrows them, for each season. Every moon is a new
+    /// season, cycling through this list.
+    pub regrow: &'static [f64],
+    /// Chance that a bush spreads to an empty neighbouring tile, when there are few bushes. This
+    /// falls off as the number of bushes gets closer to `carrying_capacity`.
+    pub chance_spread: f64,
+    /// Number of moons in a row a bush can end without berries before it dies from overgrazing.
+    /// Zero means bushes never die this way.
+    pub overgrazing_moons: usize,
+    /// Most bushes there can be at once.
+    pub carrying_capacity: usize,
+    /// Roots, which take a few hits to dig up.
+    pub roots: ResourceConfig,
+    /// Fruit trees, which are rare but more nutritious.
+    pub fruit: ResourceConfig,
+    /// Meat, left behind in a carcass when a creature starves.
+    pub meat: MeatConfig,
+}
+
+impl FoodConfig {
+    /// Chance for bushes to regrow in between moon `moon - 1` and `moon`.
+    pub fn regrow_chance(&self, moon: usize) -> f64 {
+        if self.regrow.is_empty() {
+            0.0
+        } else {
+            self.regrow[moon % self.regrow.len()]
+        }
+    }
+
+    /// Chance for each bush to spread when there are `num_bushes` of them.
+    pub fn spread_chance(&self, num_bushes: usize) -> f64 {
+        if num_bushes >= self.carrying_capacity {
+            0.0
+        } else {
+            self.chance_spread * (1.0 - num_bushes as f64 / self.carrying_capacity as f64)
+        }
+    }
+}
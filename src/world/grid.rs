@@ -4,11 +4,20 @@ use std::{
     ops::{Add, Index, IndexMut, Sub},
 };
 
-use raylib::{color::Color, drawing::RaylibDraw};
-
-use crate::{render::assets::Assets, util::TILE_SIZE};
+#[cfg(feature = "render")]
+use raylib::drawing::RaylibDraw;
+
+use crate::util::Color;
+#[cfg(feature = "render")]
+use crate::{
+    render::{
+        assets::Assets,
+        export::{Frame, Sprites},
+    },
+    util::TILE_SIZE,
+};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Pos(pub usize, pub usize);
 
 impl Add<Pos> for Pos {
@@ -33,9 +42,9 @@ impl Add<(i8, i8)> for Pos {
     }
 }
 
-impl Into<IPos> for Pos {
-    fn into(self) -> IPos {
-        IPos(self.0 as isize, self.1 as isize)
+impl From<Pos> for IPos {
+    fn from(pos: Pos) -> Self {
+        IPos(pos.0 as isize, pos.1 as isize)
     }
 }
 
@@ -76,25 +85,84 @@ impl TryInto<Pos> for IPos {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Tile {
     Empty,
     OutOfBounds,
     Bush(bool),
+    /// Roots that need this many more hits to be dug up. Zero once they have been.
+    Roots(u8),
+    /// A fruit tree, and whether it has fruit.
+    Fruit(bool),
+    /// A carcass with this much meat left on it.
+    Carcass(u8),
+    /// Food that creatures left here for later.
+    Cache(u8),
+    /// Wall built by a species, with the hits it can still take.
     Wall {
         species: usize,
         color: Color,
+        hp: u8,
     },
     Creature {
         species: usize,
         color: Color,
         food: isize,
     },
+    /// Home of a species, with the food stored in it and the hits it can still take.
+    Nest {
+        species: usize,
+        color: Color,
+        food: u8,
+        hp: u8,
+    },
+    /// Catches creatures of other species that step on it. They see it as [`Tile::Empty`].
+    Trap {
+        species: usize,
+        color: Color,
+        hp: u8,
+    },
 }
 
-impl Hash for Tile {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        core::mem::discriminant(self).hash(state);
+impl Tile {
+    /// Number of distinct [`Tile::code`]s.
+    pub const NUM_CODES: usize = 16;
+    /// Number of bits used by [`Tile::code`].
+    pub const CODE_BITS: u32 = 4;
+
+    /// Stable code, below [`Self::NUM_CODES`], of what a creature of `own_species` can tell about
+    /// the tile: empty, obstacle, bush with berries, plant without food (empty bush, dug up roots
+    /// or fruit tree without fruit), the observer itself (if `is_observer`), ally, enemy, enemy
+    /// wall, roots, fruit, carcass, cache, own nest, enemy nest, own trap and own wall. Species
+    /// are only told apart as own or other, so any number of species can be encoded. Colors, food,
+    /// hit points and how much is left of roots and carcasses are left out.
+    pub fn code(&self, own_species: Option<usize>, is_observer: bool) -> u8 {
+        match *self {
+            Tile::Empty => 0,
+            Tile::OutOfBounds => 1,
+            Tile::Bush(true) => 2,
+            Tile::Bush(false) | Tile::Roots(0) | Tile::Fruit(false) => 3,
+            Tile::Creature { .. } if is_observer => 4,
+            Tile::Creature { species, .. } if Some(species) == own_species => 5,
+            Tile::Creature { .. } => 6,
+            Tile::Wall { species, .. } if Some(species) == own_species => 15,
+            Tile::Wall { .. } => 7,
+            Tile::Roots(_) => 8,
+            Tile::Fruit(true) => 9,
+            Tile::Carcass(_) => 10,
+            Tile::Cache(_) => 11,
+            Tile::Nest { species, .. } if Some(species) == own_species => 12,
+            Tile::Nest { .. } => 13,
+            Tile::Trap { .. } => 14,
+        }
+    }
+
+    /// Whether eating from the tile gives food.
+    pub fn has_food(&self) -> bool {
+        matches!(
+            self,
+            Tile::Bush(true) | Tile::Fruit(true) | Tile::Carcass(_)
+        ) || matches!(self, Tile::Roots(hits) if *hits > 0)
     }
 }
 
@@ -105,20 +173,16 @@ impl Debug for Tile {
             Self::OutOfBounds => write!(f, "X"),
             Self::Bush(true) => write!(f, "%"),
             Self::Bush(false) => write!(f, "/"),
+            Self::Roots(0) => write!(f, "."),
+            Self::Roots(_) => write!(f, "&"),
+            Self::Fruit(true) => write!(f, "Y"),
+            Self::Fruit(false) => write!(f, "y"),
+            Self::Carcass(_) => write!(f, "m"),
+            Self::Cache(_) => write!(f, "c"),
             Self::Wall { .. } => write!(f, "#"),
             Self::Creature { .. } => write!(f, "@"),
-        }
-    }
-}
-
-impl Into<[f32; 2]> for Tile {
-    fn into(self) -> [f32; 2] {
-        match self {
-            Tile::Empty => [0.0, 0.0],
-            Tile::OutOfBounds => [1.0 / 5.0, 0.0],
-            Tile::Bush(has_food) => [2.0 / 5.0, if has_food { 1.0 } else { 0.0 }],
-            Tile::Wall { species, .. } => [3.0 / 5.0, species as f32],
-            Tile::Creature { species, .. } => [1.0, species as f32],
+            Self::Nest { .. } => write!(f, "O"),
+            Self::Trap { .. } => write!(f, "^"),
         }
     }
 }
@@ -140,10 +204,7 @@ impl<const WIDTH: usize, const HEIGHT: usize> Grid<WIDTH, HEIGHT> {
         Pos(x, y): Pos,
     ) -> [[Tile; SLICE_WIDTH]; SLICE_HEIGHT] {
         let mut slice = [[Tile::OutOfBounds; SLICE_WIDTH]; SLICE_HEIGHT];
-        let (mx, my) = (
-            (x + SLICE_WIDTH).clamp(0, WIDTH - 1),
-            (y + SLICE_HEIGHT).clamp(0, HEIGHT - 1),
-        );
+        let (mx, my) = ((x + SLICE_WIDTH).min(WIDTH), (y + SLICE_HEIGHT).min(HEIGHT));
 
         for (i, row) in self.arr[y..my].iter().enumerate() {
             for (j, tile) in row[x..mx].iter().enumerate() {
@@ -162,8 +223,8 @@ impl<const WIDTH: usize, const HEIGHT: usize> Grid<WIDTH, HEIGHT> {
         let (ox, oy) = ((-x).max(0) as usize, (-y).max(0) as usize);
         let (cx, cy) = (x.max(0) as usize, y.max(0) as usize);
         let (mx, my) = (
-            (x + SLICE_WIDTH as isize).clamp(0, WIDTH as isize - 1) as usize,
-            (y + SLICE_HEIGHT as isize).clamp(0, HEIGHT as isize - 1) as usize,
+            (x + SLICE_WIDTH as isize).clamp(0, WIDTH as isize) as usize,
+            (y + SLICE_HEIGHT as isize).clamp(0, HEIGHT as isize) as usize,
         );
 
         for (i, row) in self.arr[cy..my].iter().enumerate() {
@@ -175,10 +236,26 @@ impl<const WIDTH: usize, const HEIGHT: usize> Grid<WIDTH, HEIGHT> {
         slice
     }
 
-    pub fn arr(&self) -> [[Tile; WIDTH]; HEIGHT] {
-        self.arr
+    pub fn arr(&self) -> &[[Tile; WIDTH]; HEIGHT] {
+        &self.arr
+    }
+
+    /// Tile at `pos`, or [`Tile::OutOfBounds`] if it is outside of the grid.
+    pub fn get(&self, IPos(x, y): IPos) -> Tile {
+        if x < 0 || y < 0 || x >= WIDTH as isize || y >= HEIGHT as isize {
+            Tile::OutOfBounds
+        } else {
+            self.arr[y as usize][x as usize]
+        }
+    }
+
+    /// Every position in the grid, row by row. This doesn't borrow the grid, so tiles can be
+    /// changed along the way.
+    pub fn positions() -> impl Iterator<Item = Pos> {
+        (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| Pos(x, y)))
     }
 
+    #[cfg(feature = "render")]
     pub fn render(&self, d: &mut impl RaylibDraw, assets: &Assets) {
         for (y, row) in self.arr.iter().enumerate() {
             for (x, tile) in row.iter().enumerate() {
@@ -194,12 +271,53 @@ impl<const WIDTH: usize, const HEIGHT: usize> Grid<WIDTH, HEIGHT> {
                         y as i32 * TILE_SIZE,
                         Color::WHITE,
                     ),
+                    Tile::Roots(hits) => d.draw_texture(
+                        if *hits > 0 {
+                            &assets.roots
+                        } else {
+                            &assets.roots_dug
+                        },
+                        x as i32 * TILE_SIZE,
+                        y as i32 * TILE_SIZE,
+                        Color::WHITE,
+                    ),
+                    Tile::Fruit(has_fruit) => d.draw_texture(
+                        if *has_fruit {
+                            &assets.fruit_tree_fruit
+                        } else {
+                            &assets.fruit_tree
+                        },
+                        x as i32 * TILE_SIZE,
+                        y as i32 * TILE_SIZE,
+                        Color::WHITE,
+                    ),
+                    Tile::Carcass(_) => d.draw_texture(
+                        &assets.carcass,
+                        x as i32 * TILE_SIZE,
+                        y as i32 * TILE_SIZE,
+                        Color::WHITE,
+                    ),
+                    Tile::Cache(food) => {
+                        d.draw_texture(
+                            &assets.cache,
+                            x as i32 * TILE_SIZE,
+                            y as i32 * TILE_SIZE,
+                            Color::WHITE,
+                        );
+                        d.draw_text(
+                            &food.to_string(),
+                            x as i32 * TILE_SIZE,
+                            y as i32 * TILE_SIZE - 4,
+                            6,
+                            Color::WHITE,
+                        );
+                    }
                     Tile::Wall { color, .. } => {
                         d.draw_texture(
                             &assets.wall,
                             x as i32 * TILE_SIZE,
                             y as i32 * TILE_SIZE,
-                            color,
+                            *color,
                         );
                     }
                     Tile::Creature { color, food, .. } => {
@@ -207,7 +325,22 @@ impl<const WIDTH: usize, const HEIGHT: usize> Grid<WIDTH, HEIGHT> {
                             &assets.agent,
                             x as i32 * TILE_SIZE,
                             y as i32 * TILE_SIZE,
-                            color,
+                            *color,
+                        );
+                        d.draw_text(
+                            &food.to_string(),
+                            x as i32 * TILE_SIZE,
+                            y as i32 * TILE_SIZE - 4,
+                            6,
+                            Color::WHITE,
+                        );
+                    }
+                    Tile::Nest { color, food, .. } => {
+                        d.draw_texture(
+                            &assets.nest,
+                            x as i32 * TILE_SIZE,
+                            y as i32 * TILE_SIZE,
+                            *color,
                         );
                         d.draw_text(
                             &food.to_string(),
@@ -217,6 +350,71 @@ impl<const WIDTH: usize, const HEIGHT: usize> Grid<WIDTH, HEIGHT> {
                             Color::WHITE,
                         );
                     }
+                    Tile::Trap { color, .. } => d.draw_texture(
+                        &assets.trap,
+                        x as i32 * TILE_SIZE,
+                        y as i32 * TILE_SIZE,
+                        *color,
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Software equivalent of [`Grid::render`], used for exporting frames without a window.
+    #[cfg(feature = "render")]
+    pub fn rasterize(&self, frame: &mut Frame, sprites: &Sprites) {
+        for (y, row) in self.arr.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let (x, y) = (x as i32 * TILE_SIZE, y as i32 * TILE_SIZE);
+
+                match tile {
+                    Tile::Empty | Tile::OutOfBounds => (),
+                    Tile::Bush(has_food) => frame.draw_sprite(
+                        if *has_food {
+                            &sprites.bush_berries
+                        } else {
+                            &sprites.bush
+                        },
+                        x,
+                        y,
+                        Color::WHITE,
+                    ),
+                    Tile::Roots(hits) => frame.draw_sprite(
+                        if *hits > 0 {
+                            &sprites.roots
+                        } else {
+                            &sprites.roots_dug
+                        },
+                        x,
+                        y,
+                        Color::WHITE,
+                    ),
+                    Tile::Fruit(has_fruit) => frame.draw_sprite(
+                        if *has_fruit {
+                            &sprites.fruit_tree_fruit
+                        } else {
+                            &sprites.fruit_tree
+                        },
+                        x,
+                        y,
+                        Color::WHITE,
+                    ),
+                    Tile::Carcass(_) => frame.draw_sprite(&sprites.carcass, x, y, Color::WHITE),
+                    Tile::Cache(food) => {
+                        frame.draw_sprite(&sprites.cache, x, y, Color::WHITE);
+                        frame.draw_number(*food as isize, x, y - 4, Color::WHITE);
+                    }
+                    Tile::Wall { color, .. } => frame.draw_sprite(&sprites.wall, x, y, *color),
+                    Tile::Creature { color, food, .. } => {
+                        frame.draw_sprite(&sprites.agent, x, y, *color);
+                        frame.draw_number(*food, x, y - 4, Color::WHITE);
+                    }
+                    Tile::Nest { color, food, .. } => {
+                        frame.draw_sprite(&sprites.nest, x, y, *color);
+                        frame.draw_number(*food as isize, x, y - 4, Color::WHITE);
+                    }
+                    Tile::Trap { color, .. } => frame.draw_sprite(&sprites.trap, x, y, *color),
                 }
             }
         }
@@ -252,3 +450,101 @@ impl<const WIDTH: usize, const HEIGHT: usize> From<[[Tile; WIDTH]; HEIGHT]>
         Self { arr }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{hash_map::DefaultHasher, HashSet},
+        hash::{Hash, Hasher},
+    };
+
+    use super::Tile;
+    use crate::util::Color;
+
+    fn hash(tile: Tile) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        tile.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// One tile of every code, seen by a creature of species `own` with enemies of species
+    /// `other`, along with whether it is the observer.
+    fn tiles(own: usize, other: usize) -> Vec<(Tile, bool)> {
+        let creature = |species| Tile::Creature {
+            species,
+            color: Color::BLACK,
+            food: 0,
+        };
+        let wall = |species| Tile::Wall {
+            species,
+            color: Color::BLACK,
+            hp: 1,
+        };
+        let nest = |species| Tile::Nest {
+            species,
+            color: Color::BLACK,
+            food: 0,
+            hp: 1,
+        };
+
+        vec![
+            (Tile::Empty, false),
+            (Tile::OutOfBounds, false),
+            (Tile::Bush(true), false),
+            (Tile::Bush(false), false),
+            (creature(own), true),
+            (creature(own), false),
+            (creature(other), false),
+            (wall(other), false),
+            (Tile::Roots(2), false),
+            (Tile::Fruit(true), false),
+            (Tile::Carcass(1), false),
+            (Tile::Cache(1), false),
+            (nest(own), false),
+            (nest(other), false),
+            (
+                Tile::Trap {
+                    species: own,
+                    color: Color::BLACK,
+                    hp: 1,
+                },
+                false,
+            ),
+            (wall(own), false),
+        ]
+    }
+
+    #[test]
+    fn bushes_hash_differently() {
+        assert_ne!(hash(Tile::Bush(true)), hash(Tile::Bush(false)));
+    }
+
+    #[test]
+    fn creatures_of_different_species_hash_differently() {
+        let creature = |species| Tile::Creature {
+            species,
+            color: Color::WHITE,
+            food: 1,
+        };
+        assert_ne!(hash(creature(0)), hash(creature(1)));
+    }
+
+    #[test]
+    fn distinct_tiles_have_distinct_codes() {
+        let codes: Vec<u8> = tiles(0, 1)
+            .iter()
+            .map(|(tile, is_observer)| tile.code(Some(0), *is_observer))
+            .collect();
+
+        assert_eq!(codes.iter().collect::<HashSet<_>>().len(), Tile::NUM_CODES);
+        assert!(codes.iter().all(|code| (*code as usize) < Tile::NUM_CODES));
+        const { assert!(Tile::NUM_CODES <= 1 << Tile::CODE_BITS) };
+    }
+
+    #[test]
+    fn codes_do_not_depend_on_species_ids() {
+        for (few, many) in tiles(0, 1).into_iter().zip(tiles(40, 1000)) {
+            assert_eq!(few.0.code(Some(0), few.1), many.0.code(Some(40), many.1));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Turns what a creature sees into inputs for a neural network.
+
+use crate::util::{GRID_HEIGHT, GRID_WIDTH};
+
+use super::{
+    creature::CreatureState,
+    day::Phase,
+    grid::{Pos, Tile},
+    rules::Rules,
+    species::Species,
+    World,
+};
+
+/// How far a creature can see in each direction. [`CreatureState::slice`] is
+/// `2 * VIEW_RADIUS + 1` tiles wide.
+pub const VIEW_RADIUS: usize = 3;
+
+/// Number of channels per tile. The first [`TILE_KINDS`] are one-hot, indexed by [`Tile::code`].
+/// The rest hold the food of the creature, cache or nest on the tile (so the observer's own food
+/// is always at the center), the signal an ally on the tile sent, and the scent of the observer's
+/// and of other species.
+pub const TILE_CHANNELS: usize = TILE_KINDS + 4;
+/// Number of one-hot channels per tile.
+pub const TILE_KINDS: usize = Tile::NUM_CODES;
+
+/// Encodes a [`CreatureState`] as a flat list of floats.
+///
+/// Every tile within `radius` of the creature gets [`TILE_CHANNELS`] channels, row by row.
+/// Creatures are encoded relative to the observer rather than by species id, so a model trained
+/// for one species can control another. These are followed by the creature's food scaled to
+/// `max_food`, how far into the moon it is (from 0 to 1), whether it is night, the offsets to the nearest ally
+/// and enemy in view, scaled to `radius`, and the offsets to the `memory` nearest bushes the
+/// creature remembers, scaled to the grid size. Missing offsets are zero.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ObservationEncoder {
+    /// How many tiles around the creature are included. At most [`VIEW_RADIUS`].
+    pub radius: usize,
+    /// Amount of food that is encoded as 1.
+    pub max_food: f32,
+    /// Number of remembered bushes to include. Zero leaves out memory entirely.
+    pub memory: usize,
+    /// Signal that is encoded as 1.
+    pub max_signal: f32,
+}
+
+impl ObservationEncoder {
+    pub const DEFAULT: Self = Self {
+        radius: VIEW_RADIUS,
+        max_food: 4.0,
+        memory: 2,
+        max_signal: 4.0,
+    };
+
+    /// Number of floats in an observation.
+    pub const fn size(&self) -> usize {
+        let width = self.radius * 2 + 1;
+        width * width * TILE_CHANNELS + 7 + self.memory * 2
+    }
+
+    pub fn encode<R: Rules>(&self, state: &CreatureState<R>) -> Vec<f32> {
+        let mut observation = vec![0.0; self.size()];
+        self.encode_into(state, &mut observation);
+        observation
+    }
+
+    /// Writes the observation of `state` into `out`, which has to be exactly [`Self::size`] long.
+    pub fn encode_into<R: Rules>(&self, state: &CreatureState<R>, out: &mut [f32]) {
+        self.write(
+            View {
+                tile: |pos| state.slice[pos],
+                signals: &state.signals,
+                own_scent: &state.own_scent,
+                other_scent: &state.other_scent,
+                food: state.food,
+                time: state.time,
+                moon_len: state.moon_len,
+                phase: state.phase,
+                remembered_food: state.remembered_food.iter().copied(),
+            },
+            out,
+        );
+    }
+
+    /// Writes the observation of member `index` of `species` into `out`, reading straight from
+    /// `world` instead of building its [`CreatureState`] first. Gives the same observation as
+    /// [`Self::encode_into`] with [`CreatureState::new`].
+    pub fn encode_creature<R: Rules>(
+        &self,
+        world: &World<R>,
+        species: &Species,
+        time: usize,
+        index: usize,
+        out: &mut [f32],
+    ) {
+        let phase = species.day_cycle.phase(time);
+        let radius = species.view_radius(phase);
+        let (own_scent, other_scent) = world.scent.view(species.id, species.members[index]);
+
+        self.write(
+            View {
+                tile: |pos| species.get_visible_tile(&world.grid, index, radius, pos),
+                signals: &species.get_visible_signals(index, phase),
+                own_scent: &own_scent,
+                other_scent: &other_scent,
+                food: species.get_food(&world.grid, index),
+                time,
+                moon_len: world.config.moon_len,
+                phase,
+                remembered_food: species.recall_nearest_food(index),
+            },
+            out,
+        );
+    }
+
+    /// Writes the observations of all members of `species`, one after the other, into `out`,
+    /// which has to be [`Self::size`] times the number of members long.
+    pub fn encode_species<R: Rules>(
+        &self,
+        world: &World<R>,
+        species: &Species,
+        time: usize,
+        out: &mut [f32],
+    ) {
+        assert_eq!(
+            out.len(),
+            self.size() * species.members.len(),
+            "Wrong observation batch size."
+        );
+
+        for (index, out) in out.chunks_exact_mut(self.size()).enumerate() {
+            self.encode_creature(world, species, time, index, out);
+        }
+    }
+
+    fn write(
+        &self,
+        view: View<impl Fn(Pos) -> Tile, impl Iterator<Item = (isize, isize)>>,
+        out: &mut [f32],
+    ) {
+        assert!(
+            self.radius <= VIEW_RADIUS,
+            "Observation radius {} is larger than the view radius {VIEW_RADIUS}.",
+            self.radius
+        );
+        assert_eq!(out.len(), self.size(), "Wrong observation buffer size.");
+        out.fill(0.0);
+
+        let own_species = match (view.tile)(Pos(VIEW_RADIUS, VIEW_RADIUS)) {
+            Tile::Creature { species, .. } => Some(species),
+            _ => None,
+        };
+
+        let width = self.radius * 2 + 1;
+        let offset = VIEW_RADIUS - self.radius;
+        let mut nearest_ally: Option<(usize, isize, isize)> = None;
+        let mut nearest_enemy: Option<(usize, isize, isize)> = None;
+
+        for y in 0..width {
+            for x in 0..width {
+                let tile = (view.tile)(Pos(x + offset, y + offset));
+                let (dx, dy) = (
+                    x as isize - self.radius as isize,
+                    y as isize - self.radius as isize,
+                );
+
+                let channels = &mut out[(y * width + x) * TILE_CHANNELS..][..TILE_CHANNELS];
+                channels[tile.code(own_species, (dx, dy) == (0, 0)) as usize] = 1.0;
+                channels[TILE_KINDS + 1] =
+                    view.signals[y + offset][x + offset] as f32 / self.max_signal;
+                channels[TILE_KINDS + 2] = view.own_scent[y + offset][x + offset] as f32 / 255.0;
+                channels[TILE_KINDS + 3] = view.other_scent[y + offset][x + offset] as f32 / 255.0;
+
+                if let Tile::Cache(food) | Tile::Nest { food, .. } = tile {
+                    channels[TILE_KINDS] = self.scale_food(food as isize);
+                }
+                if let Tile::Creature { species, food, .. } = tile {
+                    channels[TILE_KINDS] = self.scale_food(food);
+                    if (dx, dy) == (0, 0) {
+                        continue;
+                    }
+
+                    let nearest = if Some(species) == own_species {
+                        &mut nearest_ally
+                    } else {
+                        &mut nearest_enemy
+                    };
+                    let distance = dx.unsigned_abs() + dy.unsigned_abs();
+                    if nearest.is_none_or(|(d, ..)| distance < d) {
+                        *nearest = Some((distance, dx, dy));
+                    }
+                }
+            }
+        }
+
+        let rest = &mut out[width * width * TILE_CHANNELS..];
+        rest[0] = self.scale_food(view.food);
+        rest[1] = (view.time as f32 / view.moon_len.max(1) as f32).clamp(0.0, 1.0);
+        rest[2] = if view.phase == Phase::Night { 1.0 } else { 0.0 };
+
+        let scale = self.radius.max(1) as f32;
+        for (i, nearest) in [nearest_ally, nearest_enemy].into_iter().enumerate() {
+            if let Some((_, dx, dy)) = nearest {
+                rest[3 + i * 2] = dx as f32 / scale;
+                rest[4 + i * 2] = dy as f32 / scale;
+            }
+        }
+
+        for (i, (dx, dy)) in view.remembered_food.take(self.memory).enumerate() {
+            rest[7 + i * 2] = dx as f32 / GRID_WIDTH as f32;
+            rest[8 + i * 2] = dy as f32 / GRID_HEIGHT as f32;
+        }
+    }
+
+    fn scale_food(&self, food: isize) -> f32 {
+        (food as f32 / self.max_food).clamp(-1.0, 1.0)
+    }
+}
+
+impl Default for ObservationEncoder {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// What a creature sees, either copied into a [`CreatureState`] or read from the world as needed.
+struct View<'a, T, M> {
+    /// Tile of the 7x7 view, with the creature at (3, 3).
+    tile: T,
+    signals: &'a [[u8; 7]; 7],
+    own_scent: &'a [[u8; 7]; 7],
+    other_scent: &'a [[u8; 7]; 7],
+    food: isize,
+    time: usize,
+    moon_len: usize,
+    phase: Phase,
+    /// Offsets to remembered food, nearest first.
+    remembered_food: M,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ObservationEncoder;
+    use crate::{
+        policy::{Policy, RandomPolicy},
+        util::{GRID_HEIGHT, GRID_WIDTH, HEPT32},
+        world::{
+            creature::CreatureState,
+            grid::{Pos, Tile},
+            tests::CONFIG,
+            World,
+        },
+    };
+
+    #[test]
+    fn encoding_from_the_world_matches_the_creature_state() {
+        let encoder = ObservationEncoder::DEFAULT;
+        let mut world = World::new(CONFIG);
+
+        for _ in 0..CONFIG.moon_len * 3 {
+            let time = world.moon_step();
+            for species in &world.species {
+                let mut batch = vec![0.0; encoder.size() * species.members.len()];
+                encoder.encode_species(&world, species, time, &mut batch);
+
+                for (index, observation) in batch.chunks(encoder.size()).enumerate() {
+                    let state = CreatureState::new(&world, species, time, index);
+                    assert_eq!(observation, encoder.encode(&state));
+                }
+            }
+
+            world.step(|_, state| RandomPolicy.choose_action(state));
+        }
+    }
+
+    #[test]
+    fn view_reaches_the_far_edges_of_the_grid() {
+        let mut world = World::new(CONFIG);
+        let corner = Pos(GRID_WIDTH - 1, GRID_HEIGHT - 1);
+        let species = &mut world.species[0];
+        world.grid[species.members[0]] = Tile::Empty;
+        species.members[0] = corner;
+        world.grid[corner] = Tile::Creature {
+            species: 0,
+            color: HEPT32::RED,
+            food: 0,
+        };
+
+        let slice = world.species[0].get_view_slice(&world.grid, 0);
+        assert!(matches!(slice[Pos(3, 3)], Tile::Creature { .. }));
+        assert_eq!(slice[Pos(4, 3)], Tile::OutOfBounds);
+        assert_eq!(slice[Pos(3, 4)], Tile::OutOfBounds);
+        assert_eq!(
+            slice[Pos(2, 2)],
+            world.grid[Pos(GRID_WIDTH - 2, GRID_HEIGHT - 2)]
+        );
+    }
+}
@@ -0,0 +1,812 @@
+use std::hash::Hash;
+
+use crate::util::{GRID_HEIGHT, GRID_WIDTH};
+
+use super::{
+    creature::{CreatureAction, CreatureState},
+    grid::{Grid, IPos, Pos, Tile},
+    World,
+};
+
+/// The rules of the game. [`World`] and the training code are generic over this trait, so
+/// variations of the game can be prototyped by implementing it on a new type.
+///
+/// All functions are associated functions rather than methods, because rurel asks states for
+/// their reward and actions without giving access to anything else.
+pub trait Rules: Clone + Eq + Hash + Send + Sized + 'static {
+    /// Called once every creature has acted. Responsible for removing dead creatures.
+    fn finish_step(world: &mut World<Self>);
+    /// Called at the end of every moon, after the last step.
+    fn finish_moon(world: &mut World<Self>);
+    /// Applies the action chosen by creature `index` of species `species` to the world.
+    fn handle_action(world: &mut World<Self>, species: usize, action: CreatureAction, index: usize);
+    /// Actions that a creature in `state` is allowed to take. Must never be empty.
+    fn actions(state: &CreatureState<Self>) -> Vec<CreatureAction>;
+    fn reward(state: &CreatureState<Self>) -> f64;
+}
+
+/// The standard game: creatures gather berries from bushes and have to eat two food every moon.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct DefaultRules;
+
+impl Rules for DefaultRules {
+    fn finish_step(world: &mut World<Self>) {
+        for species in &mut world.species {
+            let carcass = match world.config.food.meat.bites {
+                0 => Tile::Empty,
+                meat => Tile::Carcass(meat),
+            };
+
+            let mut indices = Vec::new();
+            let mut clear_pos = Vec::new();
+            for (i, creature) in species.members.iter().enumerate() {
+                if let Tile::Creature { food, .. } = world.grid[*creature] {
+                    if food < 0 {
+                        clear_pos.push((*creature, carcass));
+                        indices.push(i);
+                    }
+                } else {
+                    clear_pos.push((*creature, Tile::Empty));
+                    indices.push(i);
+                }
+            }
+
+            indices.reverse();
+            for i in indices {
+                species.members.remove(i);
+                species.forget_member(i);
+            }
+
+            for (pos, tile) in clear_pos {
+                world.grid[pos] = tile;
+            }
+        }
+    }
+
+    fn finish_moon(world: &mut World<Self>) {
+        let food = world.config.food;
+        let grid = &mut world.grid;
+        let bare_moons = &mut world.bare_moons;
+        let mut bushes = Vec::new();
+        let mut nests = Vec::new();
+
+        for pos in Grid::<GRID_WIDTH, GRID_HEIGHT>::positions() {
+            let Pos(x, y) = pos;
+            match grid[pos] {
+                Tile::Bush(true) => {
+                    bare_moons[y][x] = 0;
+                    bushes.push(pos);
+                }
+                Tile::Bush(false) => {
+                    bare_moons[y][x] += 1;
+
+                    if food.overgrazing_moons > 0 && bare_moons[y][x] >= food.overgrazing_moons {
+                        grid[pos] = Tile::Empty;
+                        bare_moons[y][x] = 0;
+                        continue;
+                    }
+
+                    if rand::random::<f64>() < food.regrow_chance(world.moon) {
+                        grid[pos] = Tile::Bush(true);
+                    }
+                    bushes.push(pos);
+                }
+                Tile::Roots(0) if rand::random::<f64>() < food.roots.regrow => {
+                    grid[pos] = Tile::Roots(food.roots.hits);
+                }
+                Tile::Fruit(false) if rand::random::<f64>() < food.fruit.regrow => {
+                    grid[pos] = Tile::Fruit(true);
+                }
+                Tile::Carcass(_) if rand::random::<f64>() < food.meat.rot => {
+                    grid[pos] = Tile::Empty;
+                }
+                Tile::Creature {
+                    food,
+                    species,
+                    color,
+                } => {
+                    grid[pos] = Tile::Creature {
+                        species,
+                        color,
+                        food: food - 2,
+                    };
+                }
+                Tile::Wall { hp, .. } if hp <= world.config.build.wall_decay => {
+                    grid[pos] = Tile::Empty;
+                }
+                Tile::Wall { species, color, hp } => {
+                    grid[pos] = Tile::Wall {
+                        species,
+                        color,
+                        hp: hp - world.config.build.wall_decay,
+                    };
+                }
+                Tile::Nest { food, .. }
+                    if world.config.build.hatch_cost > 0
+                        && food as isize >= world.config.build.hatch_cost =>
+                {
+                    nests.push(pos);
+                }
+                _ => (),
+            }
+        }
+
+        let mut num_bushes = bushes.len();
+        for bush in bushes {
+            if rand::random::<f64>() >= food.spread_chance(num_bushes) {
+                continue;
+            }
+
+            let direction = CreatureAction::DIRECTIONS
+                [rand::random::<usize>() % CreatureAction::DIRECTIONS.len()];
+            if (bush.0 == 0 && direction.0 < 0) || (bush.1 == 0 && direction.1 < 0) {
+                continue;
+            }
+
+            let pos = bush + direction;
+            if let Tile::Empty = grid[pos] {
+                grid[pos] = Tile::Bush(false);
+                bare_moons[pos.1][pos.0] = 0;
+                num_bushes += 1;
+            }
+        }
+
+        for nest in nests {
+            let Tile::Nest {
+                species,
+                color,
+                food,
+                hp,
+            } = grid[nest]
+            else {
+                continue;
+            };
+
+            let free = CreatureAction::DIRECTIONS.into_iter().find(|direction| {
+                !((nest.0 == 0 && direction.0 < 0) || (nest.1 == 0 && direction.1 < 0))
+                    && grid[nest + *direction] == Tile::Empty
+            });
+            if let Some(direction) = free {
+                grid[nest] = Tile::Nest {
+                    species,
+                    color,
+                    food: (food as isize - world.config.build.hatch_cost) as u8,
+                    hp,
+                };
+                grid[nest + direction] = Tile::Creature {
+                    species,
+                    color,
+                    food: 0,
+                };
+                world.species[species].members.push(nest + direction);
+            }
+        }
+
+        Self::finish_step(world)
+    }
+
+    fn handle_action(
+        world: &mut World<Self>,
+        species: usize,
+        action: CreatureAction,
+        index: usize,
+    ) {
+        let grid = &mut world.grid;
+        let species = &mut world.species[species];
+        let members = &mut species.members;
+        let stuck = &mut species.stuck;
+        stuck.resize(members.len(), 0);
+
+        if stuck[index] > 0 {
+            stuck[index] -= 1;
+            if let CreatureAction::Move(..) = action {
+                return;
+            }
+        }
+
+        // Traps of other species look empty, so creatures can try to walk, build or drop food onto
+        // them. The trap catches them instead.
+        let reach = match action {
+            CreatureAction::Move(x, y)
+            | CreatureAction::BuildWall(x, y)
+            | CreatureAction::BuildNest(x, y)
+            | CreatureAction::BuildTrap(x, y)
+            | CreatureAction::DropFood(x, y) => Some(members[index] + (x, y)),
+            _ => None,
+        };
+        if let Some(target) = reach {
+            if let Tile::Trap {
+                species: owner,
+                color,
+                hp,
+            } = grid[target]
+            {
+                if owner != species.id {
+                    grid[target] = if hp > 1 {
+                        Tile::Trap {
+                            species: owner,
+                            color,
+                            hp: hp - 1,
+                        }
+                    } else {
+                        Tile::Empty
+                    };
+                    change_food(grid, members[index], -species.build.trap_damage);
+                    stuck[index] = species.build.trap_steps;
+                    return;
+                }
+            }
+        }
+
+        match action {
+            CreatureAction::Move(x, y) => {
+                let target = members[index] + (x, y);
+                match grid[target] {
+                    Tile::Wall { species: owner, .. } if owner == species.id => {
+                        let beyond: Result<Pos, ()> =
+                            (Into::<IPos>::into(target) + IPos(x as isize, y as isize)).try_into();
+                        if let Ok(beyond) = beyond {
+                            if grid[beyond] == Tile::Empty {
+                                grid[beyond] = grid[members[index]];
+                                grid[members[index]] = Tile::Empty;
+                                members[index] = beyond;
+                            }
+                        }
+                    }
+                    _ => {
+                        grid[target] = grid[members[index]];
+                        grid[members[index]] = Tile::Empty;
+                        members[index] = target;
+                    }
+                }
+            }
+            CreatureAction::Attack(x, y) => {
+                let food = species.food;
+                let (target, nutrition) = match grid[members[index] + (x, y)] {
+                    Tile::Empty => (Tile::Empty, 1),
+                    Tile::OutOfBounds => (Tile::Empty, 1),
+                    Tile::Bush(true) => (Tile::Bush(false), food.berry_nutrition),
+                    Tile::Bush(false) => (Tile::Empty, 1),
+                    Tile::Roots(0) => (Tile::Roots(0), 0),
+                    Tile::Roots(1) => (Tile::Roots(0), food.roots.nutrition),
+                    Tile::Roots(hits) => (Tile::Roots(hits - 1), 0),
+                    Tile::Fruit(true) => (Tile::Fruit(false), food.fruit.nutrition),
+                    Tile::Fruit(false) => (Tile::Fruit(false), 0),
+                    Tile::Carcass(0 | 1) => (Tile::Empty, food.meat.nutrition),
+                    Tile::Carcass(meat) => (Tile::Carcass(meat - 1), food.meat.nutrition),
+                    Tile::Cache(_) => (Tile::Empty, 1),
+                    Tile::Wall { hp: 0 | 1, .. } => (Tile::Empty, 0),
+                    Tile::Wall { species, color, hp } => (
+                        Tile::Wall {
+                            species,
+                            color,
+                            hp: hp - 1,
+                        },
+                        0,
+                    ),
+                    Tile::Nest {
+                        food: 0, hp: 0 | 1, ..
+                    } => (Tile::Empty, 0),
+                    Tile::Nest {
+                        food, hp: 0 | 1, ..
+                    } => (Tile::Cache(food), 0),
+                    Tile::Nest {
+                        species,
+                        color,
+                        food,
+                        hp,
+                    } => (
+                        Tile::Nest {
+                            species,
+                            color,
+                            food,
+                            hp: hp - 1,
+                        },
+                        0,
+                    ),
+                    Tile::Trap { hp: 0 | 1, .. } => (Tile::Empty, 0),
+                    Tile::Trap { species, color, hp } => (
+                        Tile::Trap {
+                            species,
+                            color,
+                            hp: hp - 1,
+                        },
+                        0,
+                    ),
+                    Tile::Creature {
+                        species,
+                        color,
+                        food,
+                    } => (
+                        Tile::Creature {
+                            species,
+                            color,
+                            food: food - 1,
+                        },
+                        1,
+                    ),
+                };
+                grid[members[index] + (x, y)] = target;
+                grid[members[index]] = match grid[members[index]] {
+                    Tile::Creature {
+                        species,
+                        color,
+                        food,
+                    } => Tile::Creature {
+                        species,
+                        color,
+                        food: food + nutrition,
+                    },
+                    _ => panic!(
+                        "Expected creature at position {}. (Trying to give food from attack)",
+                        members[index]
+                    ),
+                };
+            }
+            CreatureAction::BuildWall(x, y) => {
+                grid[members[index] + (x, y)] = Tile::Wall {
+                    species: species.id,
+                    color: species.color,
+                    hp: species.build.wall.hp,
+                };
+                change_food(grid, members[index], -species.build.wall.cost);
+            }
+            CreatureAction::RepairWall(x, y) => {
+                let target = members[index] + (x, y);
+                if let Tile::Wall { color, hp, .. } = grid[target] {
+                    grid[target] = Tile::Wall {
+                        species: species.id,
+                        color,
+                        hp: (hp + 1).min(species.build.wall.hp),
+                    };
+                    change_food(grid, members[index], -species.build.repair_cost);
+                }
+            }
+            CreatureAction::BuildNest(x, y) => {
+                grid[members[index] + (x, y)] = Tile::Nest {
+                    species: species.id,
+                    color: species.color,
+                    food: 0,
+                    hp: species.build.nest.hp,
+                };
+                change_food(grid, members[index], -species.build.nest.cost);
+            }
+            CreatureAction::BuildTrap(x, y) => {
+                grid[members[index] + (x, y)] = Tile::Trap {
+                    species: species.id,
+                    color: species.color,
+                    hp: species.build.trap.hp,
+                };
+                change_food(grid, members[index], -species.build.trap.cost);
+            }
+            CreatureAction::DropFood(x, y) => {
+                let target = members[index] + (x, y);
+                grid[target] = match grid[target] {
+                    Tile::Cache(u8::MAX) | Tile::Nest { food: u8::MAX, .. } => return,
+                    Tile::Cache(food) => Tile::Cache(food + 1),
+                    Tile::Nest {
+                        species,
+                        color,
+                        food,
+                        hp,
+                    } => Tile::Nest {
+                        species,
+                        color,
+                        food: food + 1,
+                        hp,
+                    },
+                    _ => Tile::Cache(1),
+                };
+                change_food(grid, members[index], -1);
+            }
+            CreatureAction::PickUp(x, y) => {
+                let target = members[index] + (x, y);
+                match grid[target] {
+                    Tile::Cache(food) => {
+                        grid[target] = if food > 1 {
+                            Tile::Cache(food - 1)
+                        } else {
+                            Tile::Empty
+                        };
+                        change_food(grid, members[index], 1);
+                    }
+                    Tile::Nest {
+                        species,
+                        color,
+                        food,
+                        hp,
+                    } if food > 0 => {
+                        grid[target] = Tile::Nest {
+                            species,
+                            color,
+                            food: food - 1,
+                            hp,
+                        };
+                        change_food(grid, members[index], 1);
+                    }
+                    _ => (),
+                }
+            }
+            CreatureAction::GiveFood(x, y) => {
+                change_food(grid, members[index] + (x, y), 1);
+                change_food(grid, members[index], -1);
+            }
+            CreatureAction::DoNothing => (),
+        }
+    }
+
+    fn actions(state: &CreatureState<Self>) -> Vec<CreatureAction> {
+        let mut actions = Vec::new();
+
+        let own_species = match state.slice[Pos(3, 3)] {
+            Tile::Creature { species, .. } => Some(species),
+            _ => None,
+        };
+
+        for direction in CreatureAction::DIRECTIONS {
+            match state.slice[Pos(3, 3) + direction] {
+                Tile::Empty => {
+                    if state.stuck == 0 {
+                        actions.push(CreatureAction::Move(direction.0, direction.1));
+                    }
+                    if state.food > 0 {
+                        actions.push(CreatureAction::DropFood(direction.0, direction.1));
+                    }
+                    if state.food >= state.build.wall.cost {
+                        actions.push(CreatureAction::BuildWall(direction.0, direction.1));
+                    }
+                    if state.food >= state.build.nest.cost {
+                        actions.push(CreatureAction::BuildNest(direction.0, direction.1));
+                    }
+                    if state.food >= state.build.trap.cost {
+                        actions.push(CreatureAction::BuildTrap(direction.0, direction.1));
+                    }
+                }
+                Tile::Nest { species, food, .. } if Some(species) == own_species => {
+                    if food > 0 {
+                        actions.push(CreatureAction::PickUp(direction.0, direction.1));
+                    }
+                    if state.food > 0 && food < u8::MAX {
+                        actions.push(CreatureAction::DropFood(direction.0, direction.1));
+                    }
+                }
+                Tile::Nest { .. } => {
+                    actions.push(CreatureAction::Attack(direction.0, direction.1));
+                }
+                Tile::Cache(food) => {
+                    actions.push(CreatureAction::PickUp(direction.0, direction.1));
+                    if state.food > 0 && food < u8::MAX {
+                        actions.push(CreatureAction::DropFood(direction.0, direction.1));
+                    }
+                }
+                Tile::Creature { species, .. } => {
+                    actions.push(CreatureAction::Attack(direction.0, direction.1));
+                    if state.food > 0 && Some(species) == own_species {
+                        actions.push(CreatureAction::GiveFood(direction.0, direction.1));
+                    }
+                }
+                Tile::Wall { species, hp, .. } if Some(species) == own_species => {
+                    let beyond = Pos(3, 3) + direction + direction;
+                    if state.stuck == 0 && state.slice[beyond] == Tile::Empty {
+                        actions.push(CreatureAction::Move(direction.0, direction.1));
+                    }
+                    if state.food >= state.build.repair_cost && hp < state.build.wall.hp {
+                        actions.push(CreatureAction::RepairWall(direction.0, direction.1));
+                    }
+                }
+                Tile::Wall { .. } => {
+                    actions.push(CreatureAction::Attack(direction.0, direction.1));
+                }
+                tile if tile.has_food() => {
+                    actions.push(CreatureAction::Attack(direction.0, direction.1));
+                }
+                _ => (),
+            }
+        }
+
+        if actions.is_empty() {
+            actions.push(CreatureAction::DoNothing);
+        }
+
+        actions
+    }
+
+    fn reward(state: &CreatureState<Self>) -> f64 {
+        (2 - state.food) as f64 * -(state.time as f64) * 1000.0
+    }
+}
+
+/// Adds `amount` to the food of the creature at `pos`.
+fn change_food(grid: &mut Grid<GRID_WIDTH, GRID_HEIGHT>, pos: Pos, amount: isize) {
+    grid[pos] = match grid[pos] {
+        Tile::Creature {
+            species,
+            color,
+            food,
+        } => Tile::Creature {
+            species,
+            color,
+            food: food + amount,
+        },
+        tile => {
+            panic!("Expected creature at position {pos}, got {tile:?}. (Trying to change food)")
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultRules, Rules};
+    use crate::{
+        util::{GRID_HEIGHT, GRID_WIDTH, HEPT32},
+        world::{
+            creature::CreatureAction,
+            food::{FoodConfig, MeatConfig},
+            grid::{Grid, Pos, Tile},
+            tests::{world_with, CONFIG},
+            SimConfig, World,
+        },
+    };
+
+    /// Ends a moon in which nobody did anything.
+    fn skip_moon(world: &mut World) {
+        for _ in 0..world.config.moon_len {
+            world.step(|_, _| (CreatureAction::DoNothing, 0));
+        }
+    }
+
+    #[test]
+    fn bushes_spread_to_empty_neighbours() {
+        let config = SimConfig {
+            food: FoodConfig {
+                regrow: &[],
+                chance_spread: 1.0,
+                overgrazing_moons: 0,
+                carrying_capacity: usize::MAX,
+                ..CONFIG.food
+            },
+            ..CONFIG
+        };
+        let mut world = world_with(config, &[]);
+        world.grid[Pos(5, 5)] = Tile::Bush(true);
+
+        skip_moon(&mut world);
+
+        let bushes: Vec<Pos> = Grid::<GRID_WIDTH, GRID_HEIGHT>::positions()
+            .filter(|pos| matches!(world.grid[*pos], Tile::Bush(_)))
+            .collect();
+        assert_eq!(bushes.len(), 2);
+        let new = bushes.into_iter().find(|pos| *pos != Pos(5, 5)).unwrap();
+        assert_eq!(new.0.abs_diff(5) + new.1.abs_diff(5), 1);
+        assert_eq!(world.grid[new], Tile::Bush(false));
+        assert_eq!(world.grid[Pos(5, 5)], Tile::Bush(true));
+    }
+
+    #[test]
+    fn bushes_die_when_overgrazed() {
+        let config = SimConfig {
+            food: FoodConfig {
+                regrow: &[],
+                chance_spread: 0.0,
+                overgrazing_moons: 2,
+                ..CONFIG.food
+            },
+            ..CONFIG
+        };
+        let mut world = world_with(config, &[]);
+        world.grid[Pos(5, 5)] = Tile::Bush(false);
+        world.grid[Pos(8, 8)] = Tile::Bush(true);
+
+        skip_moon(&mut world);
+        assert_eq!(world.grid[Pos(5, 5)], Tile::Bush(false));
+        assert_eq!(world.bare_moons[5][5], 1);
+
+        skip_moon(&mut world);
+        assert_eq!(world.grid[Pos(5, 5)], Tile::Empty);
+        assert_eq!(world.grid[Pos(8, 8)], Tile::Bush(true));
+    }
+
+    #[test]
+    fn starved_creatures_leave_carcasses_that_rot() {
+        let config = SimConfig {
+            food: FoodConfig {
+                meat: MeatConfig {
+                    nutrition: 1,
+                    bites: 2,
+                    rot: 1.0,
+                },
+                ..CONFIG.food
+            },
+            ..CONFIG
+        };
+        let mut world = world_with(config, &[(0, Pos(5, 5), -1)]);
+
+        world.step(|_, _| (CreatureAction::DoNothing, 0));
+        assert!(world.species[0].members.is_empty());
+        assert_eq!(world.grid[Pos(5, 5)], Tile::Carcass(2));
+
+        skip_moon(&mut world);
+        assert_eq!(world.grid[Pos(5, 5)], Tile::Empty);
+    }
+
+    #[test]
+    fn food_can_be_dropped_given_and_picked_up() {
+        let mut world = world_with(CONFIG, &[(0, Pos(5, 5), 3), (0, Pos(5, 6), 0)]);
+        // The upper creature takes `action`, while the one below it waits.
+        let step = |world: &mut World, action| {
+            world.step(|_, state| match state.slice[Pos(3, 4)] {
+                Tile::Creature { .. } => {
+                    assert!(DefaultRules::actions(state).contains(&action), "{action:?}");
+                    (action, 0)
+                }
+                _ => (CreatureAction::DoNothing, 0),
+            });
+            [0, 1].map(|index| world.species[0].get_food(&world.grid, index))
+        };
+
+        assert_eq!(step(&mut world, CreatureAction::DropFood(1, 0)), [2, 0]);
+        assert_eq!(world.grid[Pos(6, 5)], Tile::Cache(1));
+
+        assert_eq!(step(&mut world, CreatureAction::GiveFood(0, 1)), [1, 1]);
+
+        assert_eq!(step(&mut world, CreatureAction::PickUp(1, 0)), [2, 1]);
+        assert_eq!(world.grid[Pos(6, 5)], Tile::Empty);
+    }
+
+    #[test]
+    fn full_caches_take_no_more_food() {
+        let mut world = world_with(CONFIG, &[(0, Pos(5, 5), 3)]);
+        world.grid[Pos(6, 5)] = Tile::Cache(u8::MAX);
+
+        world.step(|_, state| {
+            let actions = DefaultRules::actions(state);
+            assert!(!actions.contains(&CreatureAction::DropFood(1, 0)));
+            assert!(actions.contains(&CreatureAction::PickUp(1, 0)));
+            (CreatureAction::DropFood(1, 0), 0)
+        });
+        assert_eq!(world.grid[Pos(6, 5)], Tile::Cache(u8::MAX));
+        assert_eq!(world.species[0].get_food(&world.grid, 0), 3);
+    }
+
+    #[test]
+    fn creatures_pass_through_their_own_walls_only() {
+        let mut world = world_with(CONFIG, &[(0, Pos(5, 5), 2), (1, Pos(5, 8), 2)]);
+        let wall = Tile::Wall {
+            species: 0,
+            color: HEPT32::RED,
+            hp: 2,
+        };
+        world.grid[Pos(6, 5)] = wall;
+        world.grid[Pos(6, 8)] = wall;
+
+        world.step(|id, state| {
+            let actions = DefaultRules::actions(state);
+            if id == 0 {
+                assert!(actions.contains(&CreatureAction::Move(1, 0)));
+            } else {
+                assert!(!actions.contains(&CreatureAction::Move(1, 0)));
+            }
+            (CreatureAction::Move(1, 0), 0)
+        });
+
+        assert_eq!(world.species[0].members, [Pos(7, 5)]);
+        assert_eq!(world.grid[Pos(6, 5)], wall);
+    }
+
+    #[test]
+    fn walls_are_built_repaired_and_decay() {
+        let build = CONFIG.build;
+        let mut world = world_with(CONFIG, &[(0, Pos(5, 5), 8)]);
+        let hp = |world: &World| match world.grid[Pos(6, 5)] {
+            Tile::Wall { species: 0, hp, .. } => hp,
+            tile => panic!("Expected a wall, got {tile:?}."),
+        };
+
+        world.step(|_, _| (CreatureAction::BuildWall(1, 0), 0));
+        assert_eq!(hp(&world), build.wall.hp);
+        for _ in 1..CONFIG.moon_len {
+            world.step(|_, _| (CreatureAction::DoNothing, 0));
+        }
+        assert_eq!(hp(&world), build.wall.hp - build.wall_decay);
+
+        world.step(|_, state| {
+            assert!(DefaultRules::actions(state).contains(&CreatureAction::RepairWall(1, 0)));
+            (CreatureAction::RepairWall(1, 0), 0)
+        });
+        assert_eq!(hp(&world), build.wall.hp);
+        // Besides building and repairing, it ate 2 food at the end of the moon.
+        assert_eq!(
+            world.species[0].get_food(&world.grid, 0),
+            8 - build.wall.cost - 2 - build.repair_cost
+        );
+
+        world.grid[Pos(6, 5)] = Tile::Wall {
+            species: 0,
+            color: HEPT32::RED,
+            hp: build.wall_decay,
+        };
+        skip_moon(&mut world);
+        assert_eq!(world.grid[Pos(6, 5)], Tile::Empty);
+    }
+
+    #[test]
+    fn nests_hatch_creatures_from_their_food() {
+        let mut world = world_with(CONFIG, &[(0, Pos(5, 5), 2)]);
+        let nest = Tile::Nest {
+            species: 0,
+            color: HEPT32::RED,
+            food: CONFIG.build.hatch_cost as u8 + 1,
+            hp: 1,
+        };
+        world.grid[Pos(8, 5)] = nest;
+
+        skip_moon(&mut world);
+
+        assert_eq!(world.species[0].members.len(), 2);
+        let hatched = world.species[0].members[1];
+        assert_eq!(hatched.0.abs_diff(8) + hatched.1.abs_diff(5), 1);
+        assert!(matches!(world.grid[Pos(8, 5)], Tile::Nest { food: 1, .. }));
+    }
+
+    #[test]
+    fn traps_catch_creatures_of_other_species() {
+        let trap = Tile::Trap {
+            species: 0,
+            color: HEPT32::RED,
+            hp: 2,
+        };
+        let mut world = world_with(CONFIG, &[(1, Pos(5, 5), 2)]);
+        world.grid[Pos(6, 5)] = trap;
+
+        world.step(|_, state| {
+            assert_eq!(state.slice[Pos(4, 3)], Tile::Empty);
+            (CreatureAction::Move(1, 0), 0)
+        });
+        assert_eq!(world.species[1].members, [Pos(5, 5)]);
+        assert_eq!(
+            world.species[1].get_food(&world.grid, 0),
+            2 - CONFIG.build.trap_damage
+        );
+        assert_eq!(world.species[1].get_stuck(0), CONFIG.build.trap_steps);
+        assert!(matches!(world.grid[Pos(6, 5)], Tile::Trap { hp: 1, .. }));
+
+        world.grid[Pos(6, 5)] = Tile::Empty;
+        world.step(|_, _| (CreatureAction::Move(1, 0), 0));
+        assert_eq!(world.species[1].members, [Pos(5, 5)]);
+        assert_eq!(world.species[1].get_stuck(0), CONFIG.build.trap_steps - 1);
+    }
+
+    #[test]
+    fn hidden_traps_are_not_built_over() {
+        for action in [
+            CreatureAction::BuildWall(1, 0),
+            CreatureAction::BuildNest(1, 0),
+            CreatureAction::BuildTrap(1, 0),
+            CreatureAction::DropFood(1, 0),
+        ] {
+            let mut world = world_with(CONFIG, &[(1, Pos(5, 5), 5)]);
+            world.grid[Pos(6, 5)] = Tile::Trap {
+                species: 0,
+                color: HEPT32::RED,
+                hp: 2,
+            };
+
+            world.step(|_, _| (action, 0));
+            assert!(
+                matches!(
+                    world.grid[Pos(6, 5)],
+                    Tile::Trap {
+                        species: 0,
+                        hp: 1,
+                        ..
+                    }
+                ),
+                "{action:?}"
+            );
+            assert_eq!(
+                world.species[1].get_food(&world.grid, 0),
+                5 - CONFIG.build.trap_damage
+            );
+        }
+    }
+}
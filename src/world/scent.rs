@@ -0,0 +1,187 @@
+//! Scent that creatures leave behind as they move around.
+
+#[cfg(feature = "render")]
+use raylib::drawing::RaylibDraw;
+
+use super::grid::Pos;
+use crate::util::{GRID_HEIGHT, GRID_WIDTH};
+#[cfg(feature = "render")]
+use crate::{
+    render::export::Frame,
+    util::{Color, TILE_SIZE},
+};
+
+/// Scent left on its tile by every creature at the end of each step.
+pub const DEPOSIT: f32 = 1.0;
+/// Amount of scent that is seen (and drawn) at full strength.
+pub const FULL_SCENT: f32 = 4.0;
+
+/// One layer of scent per species, on top of the grid.
+#[derive(Clone)]
+pub struct ScentMap {
+    layers: Vec<[[f32; GRID_WIDTH]; GRID_HEIGHT]>,
+}
+
+impl ScentMap {
+    pub fn new(num_species: usize) -> Self {
+        Self {
+            layers: vec![[[0.0; GRID_WIDTH]; GRID_HEIGHT]; num_species],
+        }
+    }
+
+    pub fn get(&self, species: usize, Pos(x, y): Pos) -> f32 {
+        self.layers
+            .get(species)
+            .and_then(|layer| layer.get(y))
+            .and_then(|row| row.get(x))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    pub fn deposit(&mut self, species: usize, Pos(x, y): Pos, amount: f32) {
+        if species >= self.layers.len() {
+            self.layers
+                .resize(species + 1, [[0.0; GRID_WIDTH]; GRID_HEIGHT]);
+        }
+        self.layers[species][y][x] += amount;
+    }
+
+    /// Spreads `diffusion` of the scent on every tile evenly to its neighbours, then keeps only
+    /// `decay` of all scent.
+    pub fn update(&mut self, decay: f32, diffusion: f32) {
+        for layer in &mut self.layers {
+            let old = *layer;
+
+            for (y, row) in old.iter().enumerate() {
+                for (x, scent) in row.iter().enumerate() {
+                    layer[y][x] -= scent * diffusion;
+
+                    let share = scent * diffusion / 4.0;
+                    for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                        let (nx, ny) = (x as isize + dx, y as isize + dy);
+                        if nx >= 0
+                            && ny >= 0
+                            && (nx as usize) < GRID_WIDTH
+                            && (ny as usize) < GRID_HEIGHT
+                        {
+                            layer[ny as usize][nx as usize] += share;
+                        } else {
+                            layer[y][x] += share;
+                        }
+                    }
+                }
+            }
+
+            for scent in layer.iter_mut().flatten() {
+                *scent *= decay;
+            }
+        }
+    }
+
+    /// Scent of `species` and of all other species in the 7x7 area centered on `center`, scaled
+    /// so that [`FULL_SCENT`] is 255.
+    pub fn view(&self, species: usize, center: Pos) -> ([[u8; 7]; 7], [[u8; 7]; 7]) {
+        let mut own = [[0; 7]; 7];
+        let mut others = [[0; 7]; 7];
+
+        for y in 0..7 {
+            for x in 0..7 {
+                let (tx, ty) = (
+                    center.0 as isize + x as isize - 3,
+                    center.1 as isize + y as isize - 3,
+                );
+                if tx < 0 || ty < 0 {
+                    continue;
+                }
+                let pos = Pos(tx as usize, ty as usize);
+
+                let mut other = 0.0;
+                for i in 0..self.layers.len() {
+                    if i != species {
+                        other += self.get(i, pos);
+                    }
+                }
+
+                own[y][x] = level(self.get(species, pos));
+                others[y][x] = level(other);
+            }
+        }
+
+        (own, others)
+    }
+
+    /// Draws each species' scent as a translucent overlay in its color.
+    #[cfg(feature = "render")]
+    pub fn render(&self, d: &mut impl RaylibDraw, colors: &[Color]) {
+        self.overlay(colors, |x, y, color| {
+            d.draw_rectangle(x, y, TILE_SIZE, TILE_SIZE, color)
+        });
+    }
+
+    /// Software equivalent of [`ScentMap::render`].
+    #[cfg(feature = "render")]
+    pub fn rasterize(&self, frame: &mut Frame, colors: &[Color]) {
+        self.overlay(colors, |x, y, color| {
+            for py in y..y + TILE_SIZE {
+                for px in x..x + TILE_SIZE {
+                    frame.blend(px, py, color);
+                }
+            }
+        });
+    }
+
+    #[cfg(feature = "render")]
+    fn overlay(&self, colors: &[Color], mut draw: impl FnMut(i32, i32, Color)) {
+        for (layer, color) in self.layers.iter().zip(colors) {
+            for (y, row) in layer.iter().enumerate() {
+                for (x, scent) in row.iter().enumerate() {
+                    let alpha = level(*scent) / 2;
+                    if alpha > 0 {
+                        draw(
+                            x as i32 * TILE_SIZE,
+                            y as i32 * TILE_SIZE,
+                            Color { a: alpha, ..*color },
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn level(scent: f32) -> u8 {
+    (scent / FULL_SCENT * 255.0).clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{level, DEPOSIT};
+    use crate::world::{
+        creature::CreatureAction,
+        grid::Pos,
+        tests::{world_with, CONFIG},
+    };
+
+    #[test]
+    fn creatures_leave_scent_that_spreads_and_decays() {
+        let mut world = world_with(CONFIG, &[(0, Pos(5, 5), 2), (1, Pos(10, 10), 2)]);
+        world.step(|_, _| (CreatureAction::DoNothing, 0));
+
+        let (decay, diffusion) = (CONFIG.scent_decay, CONFIG.scent_diffusion);
+        let left = DEPOSIT * (1.0 - diffusion) * decay;
+        let spread = DEPOSIT * diffusion / 4.0 * decay;
+        assert!((world.scent.get(0, Pos(5, 5)) - left).abs() < 1e-6);
+        assert!((world.scent.get(0, Pos(6, 5)) - spread).abs() < 1e-6);
+        assert_eq!(world.scent.get(0, Pos(6, 6)), 0.0);
+        assert_eq!(world.scent.get(1, Pos(5, 5)), 0.0);
+
+        let (own, others) = world.scent.view(0, Pos(5, 5));
+        assert_eq!(own[3][3], level(left));
+        assert_eq!(own[3][4], level(spread));
+        assert_eq!(others, [[0; 7]; 7]);
+
+        let (own, others) = world.scent.view(1, Pos(8, 8));
+        assert_eq!(own[5][5], level(left));
+        assert_eq!(others[0][0], level(left));
+    }
+}
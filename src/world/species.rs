@@ -1,11 +1,13 @@
-use std::{cell::RefCell, rc::Rc};
-
-use raylib::color::Color;
-
-use super::grid::{Grid, IPos, Pos, Tile};
+use super::{
+    day::{DayCycle, Phase},
+    food::FoodConfig,
+    grid::{Grid, IPos, Pos, Tile},
+    rules::Rules,
+    structure::BuildConfig,
+};
 use crate::{
-    train::CreatureAction,
-    util::{GRID_HEIGHT, GRID_WIDTH},
+    policy::PolicyKind,
+    util::{Color, GRID_HEIGHT, GRID_WIDTH},
     world::World,
 };
 
@@ -17,119 +19,283 @@ pub struct SpeciesConfig {
     pub num_creatures: usize,
     /// Number of packs
     pub num_packs: usize,
+    /// What controls the creatures
+    pub policy: PolicyKind,
+    /// Number of distinct signals creatures can send to allies in view. Zero disables signaling.
+    pub signals: u8,
+    /// Whether creatures can see as far at night as during the day.
+    pub nocturnal: bool,
+}
+
+/// What a creature remembers about tiles it can no longer see.
+#[derive(Clone, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Memory {
+    /// Tiles that had food the last time the creature saw them.
+    pub food: Vec<Pos>,
+}
+
+impl Memory {
+    /// Updates the memory with the view of a creature standing at `pos`, where `view` gives the
+    /// tile it sees at each position of its view slice.
+    fn observe(&mut self, pos: Pos, view: impl Fn(Pos) -> Tile) {
+        let corner: IPos = Into::<IPos>::into(pos) - IPos(3, 3);
+
+        for view_pos in Grid::<7, 7>::positions() {
+            let Pos(x, y) = view_pos;
+            let Ok(pos) = (corner + IPos(x as isize, y as isize)).try_into() else {
+                continue;
+            };
+
+            let remembered = self.food.iter().position(|food| *food == pos);
+            match (view(view_pos), remembered) {
+                (Tile::OutOfBounds, _) => (),
+                (tile, None) if tile.has_food() => self.food.push(pos),
+                (tile, Some(_)) if tile.has_food() => (),
+                (_, Some(i)) => {
+                    self.food.swap_remove(i);
+                }
+                (_, None) => (),
+            }
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Species {
     pub id: usize,
-    pub members: RefCell<Vec<Pos>>,
+    pub members: Vec<Pos>,
+    /// Memory of each member, in the same order as `members`.
+    pub memories: Vec<Memory>,
+    /// Signal each member sent last step (zero if none), in the same order as `members`.
+    pub signals: Vec<u8>,
+    /// Signals being sent this step. They become visible when the step ends.
+    pub emitted: Vec<u8>,
+    /// Steps each member still can't move for after being caught in a trap, in the same order as
+    /// `members`.
+    pub stuck: Vec<usize>,
+    pub num_signals: u8,
+    pub nocturnal: bool,
+    pub day_cycle: DayCycle,
+    pub food: FoodConfig,
+    pub build: BuildConfig,
     pub color: Color,
-    grid: Rc<RefCell<Grid<GRID_WIDTH, GRID_HEIGHT>>>,
 }
 
 impl Species {
-    pub fn new(id: usize, world: &World, color: Color) -> Self {
+    pub fn new<R: Rules>(id: usize, world: &World<R>, config: SpeciesConfig) -> Self {
         Self {
             id,
-            members: RefCell::new(Vec::new()),
-            grid: world.grid.clone(),
-            color,
+            members: Vec::new(),
+            memories: Vec::new(),
+            signals: Vec::new(),
+            emitted: Vec::new(),
+            stuck: Vec::new(),
+            num_signals: config.signals,
+            nocturnal: config.nocturnal,
+            day_cycle: world.config.day_cycle,
+            food: world.config.food,
+            build: world.config.build,
+            color: config.color,
         }
     }
 
-    pub fn get_view_slice(&self, index: usize) -> Grid<7, 7> {
-        let i_pos: IPos = Into::<IPos>::into(self.members.borrow()[index]) - IPos(3, 3);
+    pub fn get_view_slice(&self, grid: &Grid<GRID_WIDTH, GRID_HEIGHT>, index: usize) -> Grid<7, 7> {
+        let i_pos: IPos = Into::<IPos>::into(self.members[index]) - IPos(3, 3);
 
         if let Ok(pos) = i_pos.try_into() {
-            self.grid.borrow().slice(pos)
+            grid.slice(pos)
         } else {
-            self.grid.borrow().i_slice(i_pos)
+            grid.i_slice(i_pos)
         }
         .into()
     }
 
-    pub fn get_food(&self, index: usize) -> isize {
-        if let Tile::Creature { food, .. } = self.grid.borrow()[self.members.borrow()[index]] {
-            food
-        } else {
-            println!("{:?}", self.grid.borrow()[self.members.borrow()[index]]);
-            panic!(
-                "Expected creature at position {}. (Trying to access amount of food)",
-                self.members.borrow()[index]
-            );
+    /// The part of [`Self::get_view_slice`] that creature `index` can see during `phase`. Tiles
+    /// that are too dark to see are [`Tile::OutOfBounds`], and traps of other species look
+    /// [`Tile::Empty`].
+    pub fn get_visible_slice(
+        &self,
+        grid: &Grid<GRID_WIDTH, GRID_HEIGHT>,
+        index: usize,
+        phase: Phase,
+    ) -> Grid<7, 7> {
+        let radius = self.view_radius(phase);
+        let mut slice = self.get_view_slice(grid, index);
+
+        for y in 0..7usize {
+            for x in 0..7usize {
+                if x.abs_diff(3) > radius || y.abs_diff(3) > radius {
+                    slice[Pos(x, y)] = Tile::OutOfBounds;
+                } else if matches!(slice[Pos(x, y)], Tile::Trap { species, .. } if species != self.id)
+                {
+                    slice[Pos(x, y)] = Tile::Empty;
+                }
+            }
         }
+
+        slice
     }
 
-    pub fn handle_action(&self, action: CreatureAction, index: usize) {
-        let mut grid = self.grid.borrow_mut();
-        let mut members = self.members.borrow_mut();
+    /// Tile `pos` of [`Self::get_visible_slice`], read straight from the grid. `radius` is the
+    /// [`Self::view_radius`].
+    pub fn get_visible_tile(
+        &self,
+        grid: &Grid<GRID_WIDTH, GRID_HEIGHT>,
+        index: usize,
+        radius: usize,
+        Pos(x, y): Pos,
+    ) -> Tile {
+        if x.abs_diff(3) > radius || y.abs_diff(3) > radius {
+            return Tile::OutOfBounds;
+        }
 
-        match action {
-            CreatureAction::Move(x, y) => {
-                grid[members[index] + (x, y)] = grid[members[index]];
-                grid[members[index]] = Tile::Empty;
-                members[index] = members[index] + (x, y);
-            }
-            CreatureAction::Attack(x, y) => {
-                grid[members[index] + (x, y)] = match grid[members[index] + (x, y)] {
-                    Tile::Empty => Tile::Empty,
-                    Tile::OutOfBounds => Tile::Empty,
-                    Tile::Bush(true) => Tile::Bush(false),
-                    Tile::Bush(false) => Tile::Empty,
-                    Tile::Wall { .. } => Tile::Empty,
-                    Tile::Creature {
-                        species,
-                        color,
-                        food,
-                    } => Tile::Creature {
-                        species,
-                        color,
-                        food: food - 1,
-                    },
-                };
-                grid[members[index]] = match grid[members[index]] {
-                    Tile::Creature {
-                        species,
-                        color,
-                        food,
-                    } => Tile::Creature {
-                        species,
-                        color,
-                        food: food + 1,
-                    },
-                    _ => panic!(
-                        "Expected creature at position {}. (Trying to give food from attack)",
-                        members[index]
-                    ),
-                };
+        let center: IPos = self.members[index].into();
+        match grid.get(center + IPos(x as isize - 3, y as isize - 3)) {
+            Tile::Trap { species, .. } if species != self.id => Tile::Empty,
+            tile => tile,
+        }
+    }
+
+    /// How far members can see during `phase`.
+    pub fn view_radius(&self, phase: Phase) -> usize {
+        self.day_cycle.view_radius(phase, self.nocturnal)
+    }
+
+    pub fn get_food(&self, grid: &Grid<GRID_WIDTH, GRID_HEIGHT>, index: usize) -> isize {
+        match grid[self.members[index]] {
+            Tile::Creature { food, .. } => food,
+            tile => panic!(
+                "Expected creature at position {}, got {tile:?}. (Trying to access amount of food)",
+                self.members[index]
+            ),
+        }
+    }
+
+    /// Removes everything kept about member `index` other than its position. Rules have to call
+    /// this when removing a member.
+    pub fn forget_member(&mut self, index: usize) {
+        for list in [&mut self.signals, &mut self.emitted] {
+            if index < list.len() {
+                list.remove(index);
             }
-            CreatureAction::BuildWall(x, y) => {
-                grid[members[index] + (x, y)] = Tile::Wall {
-                    species: match grid[members[0]] {
-                        Tile::Creature { species, .. } => species,
-                        _ => panic!(
-                            "Expected creature at position {}, got {:?}. (Trying to get species)",
-                            members[0], grid[members[0]]
-                        ),
-                    },
-                    color: self.color,
-                };
-                grid[members[index]] = match grid[members[index]] {
-                    Tile::Creature {
-                        species,
-                        color,
-                        food,
-                    } => Tile::Creature {
-                        species,
-                        color,
-                        food: food - 1,
-                    },
-                    _ => panic!(
-                        "Expected creature at position {}. (Trying to take food from wall)",
-                        members[index]
-                    ),
-                };
+        }
+
+        if index < self.memories.len() {
+            self.memories.remove(index);
+        }
+        if index < self.stuck.len() {
+            self.stuck.remove(index);
+        }
+    }
+
+    /// Steps creature `index` still can't move for.
+    pub fn get_stuck(&self, index: usize) -> usize {
+        self.stuck.get(index).copied().unwrap_or(0)
+    }
+
+    /// Sends `signal` from member `index` until the end of the step, when allies in view see it.
+    /// Zero, or a signal the species can't send, sends nothing.
+    pub fn emit(&mut self, index: usize, signal: u8) {
+        self.emitted.resize(self.members.len(), 0);
+        self.emitted[index] = if signal <= self.num_signals {
+            signal
+        } else {
+            0
+        };
+    }
+
+    /// Makes the signals sent this step visible, and clears them for the next one.
+    pub fn update_signals(&mut self) {
+        self.emitted.resize(self.members.len(), 0);
+
+        self.signals.clone_from(&self.emitted);
+        self.emitted.fill(0);
+    }
+
+    /// Signals sent last step by allies creature `index` can see during `phase`, laid out like
+    /// its view slice.
+    pub fn get_visible_signals(&self, index: usize, phase: Phase) -> [[u8; 7]; 7] {
+        let mut visible = [[0; 7]; 7];
+        let radius = self.view_radius(phase) as isize;
+        let Pos(x, y) = self.members[index];
+
+        for (i, signal) in self.signals.iter().enumerate() {
+            let Some(Pos(ax, ay)) = self.members.get(i) else {
+                break;
+            };
+            let (dx, dy) = (*ax as isize - x as isize, *ay as isize - y as isize);
+
+            if *signal != 0 && i != index && dx.abs() <= radius && dy.abs() <= radius {
+                visible[(dy + 3) as usize][(dx + 3) as usize] = *signal;
             }
-            CreatureAction::DoNothing => (),
         }
+
+        visible
+    }
+
+    /// Lets every member remember what it can see during `phase`.
+    pub fn update_memories(&mut self, grid: &Grid<GRID_WIDTH, GRID_HEIGHT>, phase: Phase) {
+        let radius = self.view_radius(phase);
+        let mut memories = std::mem::take(&mut self.memories);
+        memories.resize_with(self.members.len(), Memory::default);
+
+        for (index, memory) in memories.iter_mut().enumerate() {
+            memory.observe(self.members[index], |pos| {
+                self.get_visible_tile(grid, index, radius, pos)
+            });
+        }
+
+        self.memories = memories;
+    }
+
+    /// Offsets from creature `index` to the tiles it remembers having food, nearest first.
+    pub fn recall_food(&self, index: usize) -> Vec<(isize, isize)> {
+        let Some(memory) = self.memories.get(index) else {
+            return Vec::new();
+        };
+        let Pos(x, y) = self.members[index];
+
+        let mut offsets: Vec<(isize, isize)> = memory
+            .food
+            .iter()
+            .map(|food| (food.0 as isize - x as isize, food.1 as isize - y as isize))
+            .collect();
+        offsets.sort_by_key(|(dx, dy)| dx.abs() + dy.abs());
+        offsets
+    }
+
+    /// Same as [`Self::recall_food`], but finds each next offset lazily instead of collecting and
+    /// sorting them all, so taking the first few doesn't allocate.
+    pub fn recall_nearest_food(&self, index: usize) -> impl Iterator<Item = (isize, isize)> + '_ {
+        let food = self
+            .memories
+            .get(index)
+            .map_or(&[][..], |memory| &memory.food[..]);
+        let Pos(x, y) = self.members[index];
+        let offset = move |i: usize| {
+            let Pos(fx, fy) = food[i];
+            (fx as isize - x as isize, fy as isize - y as isize)
+        };
+        // Ties are broken by the order in memory, like a stable sort would.
+        let key = move |i: usize| {
+            let (dx, dy) = offset(i);
+            (dx.abs() + dy.abs(), i)
+        };
+
+        let mut last = None;
+        std::iter::from_fn(move || {
+            let next = (0..food.len())
+                .map(key)
+                .filter(|k| last.is_none_or(|last| *k > last))
+                .min()?;
+            last = Some(next);
+            Some(offset(next.1))
+        })
+    }
+
+    pub fn total_food(&self, grid: &Grid<GRID_WIDTH, GRID_HEIGHT>) -> isize {
+        (0..self.members.len())
+            .map(|i| self.get_food(grid, i))
+            .sum()
     }
 }
@@ -0,0 +1,55 @@
+//! Structures that creatures can build.
+
+/// Cost and durability of a kind of structure.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StructureConfig {
+    /// Food it takes to build one.
+    pub cost: isize,
+    /// How many hits it takes to destroy. For traps, this is also how many creatures one can
+    /// catch.
+    pub hp: u8,
+}
+
+/// Controls walls, nests and traps.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BuildConfig {
+    /// Blocks other species. Its own species can walk through it and repair it.
+    pub wall: StructureConfig,
+    /// Hit points every wall loses in between moons. Zero means walls last until destroyed.
+    pub wall_decay: u8,
+    /// Food it takes to restore one hit point of a wall.
+    pub repair_cost: isize,
+    /// Home tile that stores food for its species, and hatches new creatures from it.
+    pub nest: StructureConfig,
+    /// Hidden from other species, and catches their creatures when they step on it.
+    pub trap: StructureConfig,
+    /// Food a nest uses up to hatch a creature at the end of a moon. Zero turns off hatching.
+    pub hatch_cost: isize,
+    /// Food a creature loses when it is caught in a trap.
+    pub trap_damage: isize,
+    /// Number of steps a caught creature can't move for.
+    pub trap_steps: usize,
+}
+
+impl BuildConfig {
+    /// Nothing is cheap enough to be built.
+    pub const DISABLED: Self = Self {
+        wall: StructureConfig {
+            cost: isize::MAX,
+            hp: 1,
+        },
+        wall_decay: 0,
+        repair_cost: isize::MAX,
+        nest: StructureConfig {
+            cost: isize::MAX,
+            hp: 1,
+        },
+        trap: StructureConfig {
+            cost: isize::MAX,
+            hp: 1,
+        },
+        hatch_cost: 0,
+        trap_damage: 0,
+        trap_steps: 0,
+    };
+}